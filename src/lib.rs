@@ -6,9 +6,42 @@ use std::io;
 pub enum Error {
     IoError(io::Error),
     ObjectError(object::Error),
+    /// A required ELF section is missing. Contains the name of the section.
     MissingElfSection(String),
+    /// The ELF's .boot section is missing, along with other requested sections. Contains the names
+    /// of every requested section (or section name pattern) which doesn't match any ELF section.
+    MissingElfSections(Vec<String>),
+    /// The ELF's .boot section is missing the executable (`SHF_EXECINSTR`) flag.
+    NotExecutable,
+    /// Two included ELF sections overlap each other. Contains the names of the earlier section,
+    /// and the later section which starts before the earlier one ends.
+    OverlappingSections(String, String),
+    /// The ELF's entrypoint isn't within the data of any included section, so the ROM would jump
+    /// to uninitialized memory. Contains the entrypoint. See [`rom::RomBuilder::strict_entry()`].
+    EntryNotInSection(u64),
+    /// An included ELF section starts at a lower address than the section the ROM's layout starts
+    /// at (normally .boot), so it can't be placed. Contains the names of the included section, and
+    /// the section the layout starts at.
+    SectionBeforeBase(String, String),
     ArtifactNotFound,
+    /// The ELF file is gzip or xz compressed, but the `compression` feature is disabled.
+    CompressionUnsupported,
     BuildFailed(String),
+    /// The provided ROM data is smaller than the header and IPL3 combined (0x1000 bytes).
+    RomTooSmall(usize),
+    /// The provided header data is smaller than a header (0x40 bytes).
+    TruncatedHeader(usize),
+    /// The provided ROM offset falls within the header/IPL3 region (below 0x1000).
+    InvalidRomOffset(usize),
+    /// The ROM is larger than a requested size. Contains the ROM's size, and the requested size.
+    RomTooLarge(usize, usize),
+    /// The provided IPL3 has an invalid length.
+    InvalidIpl3Length(usize),
+    /// The provided clock rate doesn't have its lower 4 bits set. See
+    /// [`rom::Header::set_clockrate()`].
+    InvalidClockRate(u32),
+    /// The provided patch is malformed. Contains a description of the problem.
+    InvalidPatch(String),
 }
 impl From<io::Error> for Error {
     fn from(err: io::Error) -> Self {
@@ -24,4 +57,8 @@ impl From<object::Error> for Error {
 pub type Result<T> = std::result::Result<T, Error>;
 
 pub mod rom;
-pub mod elf;
\ No newline at end of file
+pub mod elf;
+pub mod ipl3;
+pub mod ips;
+#[cfg(any(test, feature = "testutil"))]
+pub mod testutil;
\ No newline at end of file
@@ -1,183 +1,1161 @@
 use std::fs;
-use std::process::Command;
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Read, Write};
+use std::process::{Command, ExitStatus};
 use std::str::FromStr;
+use std::time::Instant;
 use bpaf::Bpaf;
 use camino::{Utf8Path, Utf8PathBuf};
 use shlex::Shlex;
-use nust64::elf::Elf;
-use nust64::rom::{Header, Rom};
+use log::{debug, error, info, warn, LevelFilter, Log, Metadata, Record};
+use nust64::elf::{Elf, ElfSection};
+use object::SymbolKind;
+use nust64::ipl3::{from_rom, validate_ipl3, LibdragonIpl3};
+use nust64::rom::{ByteOrder, CicVariant, Country, Header, Rom, RomBuilder, SectionPlacement, DEFAULT_SECTIONS};
 
-//TODO:
-// - insert file at specific location (extending ROM if necessary)
-
-const LIBDRAGON_IPL3_PROD: &'static [u8] = include_bytes!("ipl3/ipl3_prod.z64");
-const LIBDRAGON_IPL3_DEV: &'static [u8] = include_bytes!("ipl3/ipl3_dev.z64");
-const LIBDRAGON_IPL3_COMPAT: &'static [u8] = include_bytes!("ipl3/ipl3_compat.z64");
+mod config;
 
 #[derive(Debug, Clone, PartialEq, Bpaf)]
-enum LibdragonIpl3Version {
-    Compat,
-    Debug,
-    Release,
+enum LibdragonIpl3Arg {
+    Bundled(LibdragonIpl3),
     Path(Utf8PathBuf),
 }
-impl FromStr for LibdragonIpl3Version {
+impl FromStr for LibdragonIpl3Arg {
     type Err = String;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Ok(match s.to_lowercase().as_str() {
-            "compat" => Self::Compat,
-            "d" | "debug" | "dev" => Self::Debug,
-            "r" | "release" | "prod" => Self::Release,
-            s if Utf8PathBuf::from(s).is_file() => Self::Path(s.into()),
-            _ => return Err("Unable to parse libdragon IPL3 version. Expected: compat, debug, or release".into()),
+        match LibdragonIpl3::from_str(s) {
+            Ok(version) => Ok(Self::Bundled(version)),
+            Err(_) if Utf8PathBuf::from(s).is_file() => Ok(Self::Path(s.into())),
+            Err(err) => Err(format!("{err}, or a path to a custom libdragon IPL3 file")),
+        }
+    }
+}
+
+/// Prints log messages to stdout, prefixing warnings and errors.
+struct Logger;
+impl Logger {
+    fn init(level: LevelFilter) {
+        log::set_logger(&Logger).expect("failed to initialize logger");
+        log::set_max_level(level);
+    }
+}
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+    
+    fn log(&self, record: &Record) {
+        match record.level() {
+            log::Level::Error => println!("Error! {}", record.args()),
+            log::Level::Warn => println!("Warning! {}", record.args()),
+            _ => println!("{}", record.args()),
+        }
+    }
+    
+    fn flush(&self) {}
+}
+
+/// Command lines for launching supported emulators, with `>>ROM<<` replaced by the ROM's path.
+const EMULATORS: &[(&str, &str)] = &[
+    ("ares", "ares >>ROM<<"),
+    // cen64 requires the PIF ROM, which it's expected to find in the current directory
+    ("cen64", "cen64 pifdata.bin >>ROM<<"),
+    ("mupen64plus", "mupen64plus >>ROM<<"),
+    ("simple64", "simple64-gui >>ROM<<"),
+];
+
+/// Emulator to launch after ROM generation, parsed from its name in [`EMULATORS`].
+#[derive(Debug, Clone, PartialEq)]
+struct Emulator {
+    command: &'static str,
+}
+impl FromStr for Emulator {
+    type Err = String;
+    
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match EMULATORS.iter().find(|(name, _)| name.eq_ignore_ascii_case(s)) {
+            Some((_, command)) => Ok(Self { command }),
+            None => {
+                let names = EMULATORS.iter().map(|(name, _)| *name).collect::<Vec<_>>();
+                Err(format!("Unknown emulator: {s}. Expected: {}", names.join(", ")))
+            },
+        }
+    }
+}
+
+/// File to be written at a specific ROM offset, parsed from `<PATH>@<OFFSET>`.
+#[derive(Debug, Clone, PartialEq)]
+struct Insertion {
+    path: Utf8PathBuf,
+    offset: u64,
+}
+impl FromStr for Insertion {
+    type Err = String;
+    
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (path, offset) = s.rsplit_once('@').ok_or("Unable to parse insertion. Expected: <PATH>@<OFFSET>")?;
+        
+        Ok(Self {
+            path: path.into(),
+            offset: parse_u64(offset)?,
         })
     }
 }
 
+/// ELF section to be placed at a specific ROM offset, parsed from `<NAME>@<OFFSET>`.
+#[derive(Debug, Clone, PartialEq)]
+struct Placement {
+    name: String,
+    offset: u64,
+}
+impl FromStr for Placement {
+    type Err = String;
+    
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, offset) = s.rsplit_once('@').ok_or("Unable to parse placement. Expected: <NAME>@<OFFSET>")?;
+        
+        Ok(Self {
+            name: name.into(),
+            offset: parse_u64(offset)?,
+        })
+    }
+}
+
+/// Section to be injected from a file instead of the ELF, parsed from `<NAME>:<ADDR>:<PATH>`.
+#[derive(Debug, Clone, PartialEq)]
+struct SectionData {
+    name: String,
+    addr: u64,
+    path: Utf8PathBuf,
+}
+impl FromStr for SectionData {
+    type Err = String;
+    
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(3, ':');
+        let (Some(name), Some(addr), Some(path)) = (parts.next(), parts.next(), parts.next()) else {
+            return Err("Unable to parse section data. Expected: <NAME>:<ADDR>:<PATH>".into());
+        };
+        
+        Ok(Self {
+            name: name.into(),
+            addr: parse_u64(addr)?,
+            path: path.into(),
+        })
+    }
+}
+
+/// ELF section to be written to a file, parsed from `<NAME>:<PATH>`.
+#[derive(Debug, Clone, PartialEq)]
+struct Extraction {
+    name: String,
+    path: Utf8PathBuf,
+}
+impl FromStr for Extraction {
+    type Err = String;
+    
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, path) = s.split_once(':').ok_or("Unable to parse section extraction. Expected: <NAME>:<PATH>")?;
+        
+        Ok(Self {
+            name: name.into(),
+            path: path.into(),
+        })
+    }
+}
+
+/// Parses a number in either hexadecimal (prefixed with `0x`) or decimal.
+fn parse_u64(s: &str) -> Result<u64, String> {
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => s.parse(),
+    }.map_err(|_| format!("Unable to parse number: {s}"))
+}
+
+/// Parses a 32-bit address (see [`parse_u64`]).
+fn parse_address(s: String) -> Result<u32, String> {
+    parse_u64(&s)?.try_into().map_err(|_| format!("Address does not fit in 32 bits: {s}"))
+}
+
+/// Parses a size in bytes, either as a number (see [`parse_u64`]), or with a `K` or `M` suffix
+/// (e.g. `8M` for 8 MiB).
+fn parse_size(s: String) -> Result<usize, String> {
+    let upper = s.to_uppercase();
+    let (number, multiplier) = match upper.strip_suffix('M').or_else(|| upper.strip_suffix("MB")) {
+        Some(number) => (number, 1024 * 1024),
+        None => match upper.strip_suffix('K').or_else(|| upper.strip_suffix("KB")) {
+            Some(number) => (number, 1024),
+            None => (upper.as_str(), 1),
+        },
+    };
+    
+    let size = parse_u64(&number.to_lowercase())?;
+    usize::try_from(size).ok()
+        .and_then(|size| size.checked_mul(multiplier))
+        .ok_or_else(|| format!("Size is too large: {s}"))
+}
+
+/// Parses a single byte in hexadecimal, optionally prefixed with `0x`.
+fn parse_byte(s: String) -> Result<u8, String> {
+    let hex = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")).unwrap_or(&s);
+    u8::from_str_radix(hex, 16).map_err(|_| format!("Unable to parse byte: {s}. Expected a hex value from 00 to FF"))
+}
+
+/// Parses a 32-bit value in hexadecimal, optionally prefixed with `0x`.
+fn parse_hex_u32(s: String) -> Result<u32, String> {
+    let hex = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")).unwrap_or(&s);
+    u32::from_str_radix(hex, 16).map_err(|_| format!("Unable to parse value: {s}. Expected a 32-bit hex value (e.g. 80371240)"))
+}
+
+/// Parses a single ASCII character into its byte value.
+fn parse_ascii_char(s: String) -> Result<u8, String> {
+    match s.as_bytes() {
+        [byte] if byte.is_ascii() => Ok(*byte),
+        _ => Err(format!("Unable to parse character: {s}. Expected a single ASCII character")),
+    }
+}
+
+/// Parses exactly two ASCII characters into a big-endian cartridge ID.
+fn parse_cart_id(s: String) -> Result<u16, String> {
+    match s.as_bytes() {
+        [a, b] if a.is_ascii() && b.is_ascii() => Ok(u16::from_be_bytes([*a, *b])),
+        _ => Err(format!("Unable to parse cartridge ID: {s}. Expected exactly two ASCII characters")),
+    }
+}
+
 /// nust64 - ELF binary to N64 ROM converter
 #[derive(Debug, Clone, Bpaf)]
-#[bpaf(options, version, generate(args))]
+#[bpaf(options, version, generate(cli))]
+#[allow(clippy::large_enum_variant)]
+enum Cli {
+    Verify {
+        /// verify the header checksum of an existing ROM, instead of generating a new ROM
+        #[bpaf(long("verify"), argument("ROM"))]
+        rom: Utf8PathBuf,
+    },
+    Rechecksum {
+        /// recalculate the header checksum of an existing ROM, instead of generating a new ROM
+        #[bpaf(long("rechecksum"), argument("ROM"))]
+        rom: Utf8PathBuf,
+        
+        /// path to write the updated ROM to. If omitted, the ROM is updated in place.
+        #[bpaf(short('o'), long("output"), argument("PATH"))]
+        output: Option<Utf8PathBuf>,
+    },
+    Diff {
+        /// compare two existing ROMs, printing which header fields, IPL3, and binary ranges differ, instead of generating a new ROM
+        #[bpaf(long("diff"), argument("ROM"))]
+        a: Utf8PathBuf,
+        
+        #[bpaf(positional("ROM"))]
+        b: Utf8PathBuf,
+    },
+    Build(#[bpaf(external(args))] Args),
+}
+
+#[derive(Debug, Clone, Bpaf)]
+#[bpaf(generate(args))]
 struct Args {
+    /// read arguments from a TOML file (e.g. `nust64.toml`), using each argument's long name as a key (e.g. `base-section = ".boot"` or `sections = [".boot", ".text"]`). Arguments on the command line take precedence over the file.
+    #[bpaf(long, argument("PATH"))]
+    config: Option<Utf8PathBuf>,
+    
     /// command to execute before ROM generation
     #[bpaf(long("pre-exec"))]
     pre_exec: Vec<String>,
     
     /// command to execute after ROM generation (e.g. running an emulator)
     /// 
-    /// Note: any instance of `>>ROM<<` in a command string, will be replaced with the generated ROM's path
+    /// Note: any instance of `>>ROM<<` in a command string, will be replaced with the generated ROM's path.
+    /// Pre-exec, post-exec, and emulator commands also replace `$ELF` with the ELF's path, `$ROM_DIR` with the ROM's directory, `$NAME` with the ROM's name, `${VAR}` with the environment variable VAR, and `$$` with `$`.
+    /// Unset environment variables are replaced with nothing and a warning (or an error with `--strict`). Quote any which may contain spaces.
     #[bpaf(long("post-exec"))]
     post_exec: Vec<String>,
     
+    /// emulator to run the generated ROM with, after any post-exec commands
+    /// 
+    /// Valid options: ares, cen64, mupen64plus, or simple64. Use `--post-exec` for anything else.
+    #[bpaf(long, argument("NAME"))]
+    emulator: Option<Emulator>,
+    
     /// name of ELF section to include in ROM (if omitted, included sections are: .boot, .text, .rodata, .data, .assets, and .bss)
+    /// 
+    /// Note: libdragon's debug and release IPL3s load the entire ELF file, so this only applies to custom IPL3s and `--libdragon compat`
+    /// 
+    /// Names ending in `*` include every section starting with that prefix (e.g. `.text*` includes .text and .text.hot)
     #[bpaf(short, long("section"))]
     sections: Vec<String>,
     
-    /// append file to generated ROM
+    /// include every allocated ELF section (those with the `SHF_ALLOC` flag) in the ROM, in address order, instead of selecting sections by name. Non-allocated sections such as .comment and .debug_* are never included.
+    #[bpaf(long("auto-sections"))]
+    auto_sections: bool,
+    
+    /// name of the ELF section whose address the ROM's layout starts at. Any gap before the first included section is padded with zeros. If omitted, the lowest executable section included (normally .boot) is used.
+    #[bpaf(long("base-section"), argument("NAME"))]
+    base_section: Option<String>,
+    
+    /// place an included ELF section at a specific ROM offset instead of an offset based on its address, for code which is copied elsewhere at runtime (e.g. `--place .text@0x40000`). Other sections are laid out normally.
+    #[bpaf(long("place"))]
+    placements: Vec<Placement>,
+    
+    /// include the contents of a file as if it were an ELF section at the provided address, placed by address like any included section (e.g. `--section-data .assets:0x80100000:assets.bin`). This keeps large data out of the ELF file.
+    #[bpaf(long("section-data"), argument("NAME:ADDR:PATH"))]
+    section_data: Vec<SectionData>,
+    
+    /// place included ELF sections in the order they're declared in the ELF, instead of in address order. A section whose address is lower than the end of the sections before it is placed directly after the previous section, without padding. Later sections are padded relative to the highest end address so far.
+    #[bpaf(long("preserve-order"))]
+    preserve_order: bool,
+    
+    /// append file to generated ROM. Use `-` to append everything read from stdin (only once per run).
     #[bpaf(short, long("append"))]
     appends: Vec<Utf8PathBuf>,
     
-    /// name to put in ROM header (max 20 bytes)
+    /// align each appended file to a multiple of N bytes, instead of padding the ROM to a multiple of 256 bytes after each one (e.g. `--append-align 8`)
+    #[bpaf(long("append-align"), argument::<String>("N"), parse(parse_size), optional)]
+    append_align: Option<usize>,
+    
+    /// write the path, ROM offset, and length of each appended file to a JSON file
+    #[bpaf(long("append-manifest"), argument("PATH"))]
+    append_manifest: Option<Utf8PathBuf>,
+    
+    /// append every file in a directory (including subdirectories), preceded by a table of contents. See `Rom::append_filesystem` for the format.
+    #[bpaf(long("fs"), argument("DIR"))]
+    fs: Option<Utf8PathBuf>,
+    
+    /// write file at a specific ROM offset, extending the ROM if necessary (e.g. `--insert fs.bin@0x200000`)
+    #[bpaf(long("insert"))]
+    inserts: Vec<Insertion>,
+    
+    /// byte (in hex) used to pad the end of the ROM to its final size. If omitted, FF is used. Gaps between sections are always padded with 00.
+    #[bpaf(long, argument::<String>("HEX"), parse(parse_byte), optional)]
+    fill: Option<u8>,
+    
+    /// pad the ROM to an exact total size (e.g. `8M`, `16777216`, or `0x1000000`)
+    #[bpaf(long("pad-to"), argument::<String>("SIZE"), parse(parse_size), optional)]
+    pad_to: Option<usize>,
+    
+    /// don't pad ROMs smaller than 1MB to 1MB. Only for custom IPL3s which don't verify the checksum, since it always covers 1MB, so CIC IPL3s will fail to boot.
+    #[bpaf(long("no-min-pad"))]
+    no_min_pad: bool,
+    
+    /// remove trailing FF padding from the end of the ROM (but not below the 1MB covered by the checksum). Trimmed ROMs are meant for archiving or diffing, and may not boot on hardware which expects an aligned size.
+    #[bpaf(long)]
+    trim: bool,
+    
+    /// exit with an error if the final ROM is larger than this size (e.g. `64M`), after all appends, inserts, and padding
+    #[bpaf(long("max-size"), argument::<String>("SIZE"), parse(parse_size), optional)]
+    max_size: Option<usize>,
+    
+    /// entrypoint address to put in ROM header instead of the ELF's entrypoint (e.g. `0x80000400`)
+    #[bpaf(long, argument::<String>("ADDR"), parse(parse_address), optional)]
+    entry: Option<u32>,
+    
+    /// first word of the ROM header (in hex), which configures the PI's ROM timings. If omitted, 80371240 is used. Most emulators and flash cart menus detect the ROM's byte order from this value, so others may not load correctly.
+    #[bpaf(long("pi-regs"), argument::<String>("HEX"), parse(parse_hex_u32), optional)]
+    pi_regs: Option<u32>,
+    
+    /// clock rate word of the ROM header (in hex), which overrides the clock rate libultra reports. Its lower 4 bits must all be set. If omitted, 0000000F is used, which keeps the console's default.
+    #[bpaf(long, argument::<String>("HEX"), parse(parse_hex_u32), optional)]
+    clockrate: Option<u32>,
+    
+    /// name to put in ROM header (max 20 bytes). If omitted, the ELF's file name without its extension is used.
     #[bpaf(short, long)]
     name: Option<String>,
     
-    /// Path to IPL3 binary. If omitted, libdragon's open-source IPL3 is used instead (https://github.com/rasky/libdragon/blob/ipl3/boot/README.md)
+    /// country to put in ROM header, as a code character or a name (e.g. `E` or `usa`, `J` or `japan`, and `P` or `europe`). If omitted, `7` (beta) is used.
+    #[bpaf(long, argument("COUNTRY"))]
+    country: Option<Country>,
+    
+    /// two character cartridge ID to put in ROM header. If omitted, `64` is used.
+    #[bpaf(long("cart-id"), argument::<String>("ID"), parse(parse_cart_id), optional)]
+    cart_id: Option<u16>,
+    
+    /// media format character to put in ROM header (e.g. `N` for cartridge). If omitted, `R` is used.
+    #[bpaf(long("media-format"), argument::<String>("CHAR"), parse(parse_ascii_char), optional)]
+    media_format: Option<u8>,
+    
+    /// revision number to put in ROM header. If omitted, 1 is used.
+    #[bpaf(long, argument("N"))]
+    revision: Option<u8>,
+    
+    /// Path to IPL3 binary, either 4032 bytes (smaller files are padded with zeros), or 4096 bytes including a ROM header which is ignored. If omitted, libdragon's open-source IPL3 is used instead (https://github.com/rasky/libdragon/blob/ipl3/boot/README.md)
     #[bpaf(long)]
     ipl3: Option<Utf8PathBuf>,
     
+    /// Path to an existing ROM (in z64 byte order) to copy the IPL3 from, instead of `--ipl3`
+    #[bpaf(long("ipl3-from-rom"), argument("ROM"))]
+    ipl3_from_rom: Option<Utf8PathBuf>,
+    
     /// If '--ipl3' is not used, this determines which version of the libdragon open-source IPL3 is used. If omitted, the "prod" (release) version is used by default.
     /// 
     /// Valid options: compat, debug, release, or a filepath to a custom libdragon IPL3.
     #[bpaf(long)]
-    libdragon: Option<LibdragonIpl3Version>,
+    libdragon: Option<LibdragonIpl3Arg>,
     
-    /// path to ELF file
+    /// byte order of the generated ROM, which also determines its file extension. If omitted, z64 (big-endian) is used.
+    /// Can be used multiple times to write one ROM file per format, with the first being the primary format (used by `--output`, `--expect`, and `>>ROM<<`).
+    /// 
+    /// Valid options: z64, v64 (byte-swapped), or n64 (little-endian)
+    #[bpaf(long("format"))]
+    formats: Vec<ByteOrder>,
+    
+    /// path to write the generated ROM to, creating parent directories if needed. If omitted, the ROM is written next to the ELF file.
+    /// Additional formats are written next to it, with their own file extension.
+    #[bpaf(short('o'), long("output"))]
+    output: Option<Utf8PathBuf>,
+    
+    /// write the ELF's symbols to a file, sorted by address (e.g. `80000400 T main`)
+    #[bpaf(long("dump-symbols"), argument("PATH"))]
+    dump_symbols: Option<Utf8PathBuf>,
+    
+    /// write the raw data of an ELF section to a file (e.g. `--extract-section .rodata:rodata.bin`). With `--strip`, only sections which may be included in the ROM have data.
+    #[bpaf(long("extract-section"), argument("NAME:PATH"))]
+    extractions: Vec<Extraction>,
+    
+    /// print a CRC32 of the generated ROM (in z64 byte order), for detecting when its contents have changed
+    #[bpaf(long("print-hash"))]
+    print_hash: bool,
+    
+    /// skip copying the data of ELF sections which won't be included in the ROM (e.g. debug information), which speeds up loading large ELF files
+    #[bpaf(long)]
+    strip: bool,
+    
+    /// exit with an error instead of a warning if a `--section` name doesn't match any ELF sections, the ELF's entrypoint isn't within any included section, or an exec command uses an unset environment variable
     #[bpaf(long)]
-    elf: Utf8PathBuf,
+    strict: bool,
+    
+    /// print where each ELF section was placed in the generated ROM
+    #[bpaf(long)]
+    map: bool,
+    
+    /// only print warnings and errors
+    #[bpaf(short, long)]
+    quiet: bool,
+    
+    /// print additional details, such as the size of each included section, the detected CIC, and timings
+    #[bpaf(short, long)]
+    verbose: bool,
+    
+    /// generate the ROM without writing it, and print a summary instead. The ROM is validated the same as a real build, so this fails if the ELF can't produce a ROM. Pre-exec commands are still run, but post-exec commands are skipped.
+    #[bpaf(long("dry-run"))]
+    dry_run: bool,
+    
+    /// generate the ROM without writing it, and compare it to an existing ROM instead, exiting with an error at the first difference. Post-exec commands are skipped.
+    #[bpaf(long, argument("ROM"))]
+    expect: Option<Utf8PathBuf>,
+    
+    /// path to ELF file. Required, unless it's set in the `--config` file.
+    #[bpaf(long)]
+    elf: Option<Utf8PathBuf>,
+}
+impl Args {
+    /// Path to the ELF file. [`parse_cli()`] exits if it wasn't given on the command line or in the
+    /// config file, so it's always set afterwards.
+    fn elf(&self) -> &Utf8Path {
+        self.elf.as_deref().expect("the ELF path should be checked by parse_cli()")
+    }
+}
+
+/// Parses the command line, filling in any arguments not given from the `--config` file.
+fn parse_cli() -> Cli {
+    let mut cli = cli().run();
+    if let Cli::Build(args) = &mut cli {
+        if let Err(err) = merge_config(args) {
+            eprintln!("{err}");
+            std::process::exit(1);
+        }
+    }
+    
+    cli
+}
+
+/// Merges the `--config` file (if any) into `args`, then checks that the ELF path was given.
+fn merge_config(args: &mut Args) -> Result<(), String> {
+    if let Some(path) = args.config.clone() {
+        config::Config::load(&path)?.merge_into(args)?;
+    }
+    
+    match args.elf {
+        Some(_) => Ok(()),
+        None => Err("Expected --elf PATH, either on the command line or in the --config file, pass --help for usage information".into()),
+    }
 }
 
 fn main() {
-    let args = args().run();
+    let args = match parse_cli() {
+        Cli::Verify { rom } => {
+            Logger::init(LevelFilter::Info);
+            return verify(&rom);
+        },
+        Cli::Rechecksum { rom, output } => {
+            Logger::init(LevelFilter::Info);
+            return rechecksum(&rom, output.as_deref());
+        },
+        Cli::Diff { a, b } => {
+            Logger::init(LevelFilter::Info);
+            return diff(&a, &b);
+        },
+        Cli::Build(args) => args,
+    };
+    Logger::init(match (args.quiet, args.verbose) {
+        (true, _) => LevelFilter::Warn,
+        (_, true) => LevelFilter::Debug,
+        _ => LevelFilter::Info,
+    });
+    let start = Instant::now();
+    if let Some(config) = &args.config {
+        debug!("Using config: {config}");
+    }
+    
+    let outputs = output_paths(&args);
+    let (format, rom_path) = outputs[0].clone();
     
+    // a failed pre-exec (e.g. a broken build) would otherwise generate a ROM from a stale ELF
+    let name = args.name.clone().unwrap_or_else(|| Header::default_name(args.elf()));
     for pre in &args.pre_exec {
-        exec(&pre);
+        let pre = expand_exec(pre, &args, &rom_path, &name);
+        if let Some(status) = exec(&pre).filter(|status| !status.success()) {
+            error!("Pre-exec command failed ({status}), so no ROM was generated: {pre}");
+            std::process::exit(status.code().unwrap_or(1));
+        }
     }
     
-    let rom_path = args.elf.with_extension("z64");
-    let rom = match args.ipl3.clone() {
-        Some(path) => from_custom_ipl3(path, args.clone()),
-        None => from_libdragon_ipl3(args.clone()),
+    let elf = load_elf(&args);
+    if let Some(path) = &args.dump_symbols {
+        dump_symbols(&elf, path);
+    }
+    for extraction in &args.extractions {
+        elf.write_section(&extraction.name, &extraction.path).unwrap_or_else(|err| panic!("failed to extract {} to {}: {err:?}", extraction.name, extraction.path));
+        info!("Extracted {} to: {}", extraction.name, extraction.path);
+    }
+    
+    let (mut rom, layout) = match (&args.ipl3, &args.ipl3_from_rom) {
+        (Some(_), Some(_)) => {
+            error!("--ipl3 and --ipl3-from-rom can't be used together.");
+            std::process::exit(1);
+        },
+        (Some(path), None) => from_custom_ipl3(path, &elf, &args),
+        (None, Some(path)) => from_rom_ipl3(path, &elf, &args),
+        (None, None) => from_libdragon_ipl3(&elf, &args),
     };
     
-    fs::write(&rom_path, rom.to_vec()).unwrap();
+    if args.map {
+        print_map(&rom, &layout);
+    }
+    
+    let header = &mut rom.header;
+    if let Some(entry) = args.entry {
+        let in_section = layout.iter().any(|section| (section.vaddr..section.vaddr + section.len as u64).contains(&(entry as u64)));
+        if !layout.is_empty() && !in_section {
+            warn!("Entrypoint override ({entry:#010X}) is not within any section included in the ROM.");
+        }
+        header.pc = entry;
+    }
+    if let Some(pi_regs) = args.pi_regs {
+        header.pi_regs = pi_regs;
+    }
+    if let Some(clockrate) = args.clockrate {
+        if header.set_clockrate(clockrate).is_err() {
+            error!("Invalid clock rate: {clockrate:08X}. Its lower 4 bits must all be set (e.g. {:08X}).", clockrate | 0xF);
+            std::process::exit(1);
+        }
+    }
+    if let Some(country) = args.country {
+        header.country = country.to_byte();
+    }
+    if let Some(cart_id) = args.cart_id {
+        header.cart_id = cart_id;
+    }
+    if let Some(revision) = args.revision {
+        header.revision = revision;
+    }
+    if let Some(media_format) = args.media_format {
+        // 64DD disk images use a checksum of zero, so it's updated now rather than only if a
+        // later change to the binary happens to update it
+        rom.header.media_format = media_format;
+        rom.update_checksum();
+    }
+    
+    if args.appends.iter().filter(|append| *append == "-").count() > 1 {
+        error!("Only one `--append -` can read from stdin.");
+        std::process::exit(1);
+    }
+    let mut appended = vec![];
+    for append in &args.appends {
+        let data = match append.as_str() {
+            "-" => {
+                let mut data = vec![];
+                io::stdin().read_to_end(&mut data).expect("failed to read append data from stdin");
+                data
+            },
+            _ => fs::read(append).unwrap_or_else(|_| panic!("failed to read append file: {append}")),
+        };
+        let offset = match args.append_align {
+            Some(align) => rom.append_aligned(&data, align),
+            None => rom.append(&data),
+        };
+        let name = if append == "-" { "stdin" } else { append.as_str() };
+        info!("Appended {name} at ROM offset {offset:#X} ({:#X} bytes)", data.len());
+        appended.push((append, offset, data.len()));
+    }
+    if let Some(path) = &args.append_manifest {
+        write_append_manifest(&appended, path);
+    }
+    if let Some(dir) = &args.fs {
+        let mut entries = vec![];
+        read_filesystem(dir, dir, &mut entries);
+        entries.sort();
+        
+        let offset = rom.append_filesystem(&entries);
+        info!("Appended filesystem {dir} ({} files) with table of contents at ROM offset {offset:#X}", entries.len());
+    }
+    
+    for insert in &args.inserts {
+        let data = fs::read(&insert.path).unwrap_or_else(|_| panic!("failed to read insert file: {}", insert.path));
+        rom.insert_at(insert.offset as usize, &data).unwrap_or_else(|err| panic!("failed to insert {}: {err:?}", insert.path));
+    }
+    
+    if args.trim {
+        let removed = rom.trim_padding();
+        debug!("Trimmed {removed:#X} bytes of padding from the end of the ROM");
+    }
+    // appends, inserts, and trimming may leave the binary at any length, which PI DMA can't copy
+    let padding = rom.align_binary(8);
+    if padding > 0 {
+        debug!("Padded the binary with {padding:#X} bytes to align it to 8 bytes");
+    }
+    if let Some(size) = args.pad_to {
+        rom.pad_to(size).unwrap_or_else(|err| panic!("failed to pad ROM: {err:?}"));
+    }
+    
+    if let Some(max_size) = args.max_size {
+        if rom.total_size() > max_size {
+            error!("ROM is {:#X} bytes, which exceeds the maximum size of {max_size:#X} bytes.", rom.total_size());
+            std::process::exit(1);
+        }
+    }
+    
+    if args.dry_run {
+        return print_summary(&rom, &layout, &rom_path);
+    }
+    if let Some(path) = &args.expect {
+        return expect_rom(&rom, format, path);
+    }
+    
+    for (format, path) in &outputs {
+        write_rom(&rom, *format, path);
+    }
     let rom_path = rom_path.canonicalize_utf8().unwrap_or(rom_path);
-    println!("Generated ROM at: {rom_path}");
+    log_cic(&rom);
+    debug!("Generated ROM in {:?}", start.elapsed());
+    if args.print_hash {
+        println!("ROM hash (CRC32): {:#010X}", rom.content_hash());
+    }
     
-    for post in args.post_exec {
-        exec(&post.replace(">>ROM<<", rom_path.to_string().as_str()));
+    let name = rom.info().name;
+    for post in &args.post_exec {
+        let post = expand_exec(post, &args, &rom_path, &name);
+        if let Some(status) = exec(&post).filter(|status| !status.success()) {
+            warn!("Post-exec command failed ({status}): {post}");
+        }
+    }
+    if let Some(emulator) = &args.emulator {
+        let command = expand_exec(emulator.command, &args, &rom_path, &name);
+        if let Some(status) = exec(&command).filter(|status| !status.success()) {
+            warn!("Emulator exited with an error ({status}): {command}");
+        }
     }
 }
 
-fn from_custom_ipl3<P: AsRef<Utf8Path>>(ipl3_path: P, args: Args) -> Rom {
-    let ipl3_path = ipl3_path.as_ref();
-    let elf_path = args.elf;
+/// Path to write the ROM to for each requested format (without duplicates), starting with the
+/// primary format. Exits with an error if two formats would be written to the same path.
+fn output_paths(args: &Args) -> Vec<(ByteOrder, Utf8PathBuf)> {
+    let mut formats: Vec<ByteOrder> = vec![];
+    for format in &args.formats {
+        if !formats.contains(format) {
+            formats.push(*format);
+        }
+    }
+    if formats.is_empty() {
+        formats.push(ByteOrder::default());
+    }
     
-    let ipl3 = fs::read(ipl3_path).expect(&format!("IPL3 does not exist: {ipl3_path}"));
-    if ipl3.len() < 4032 {
-        println!("Warning! Provided IPL3 is smaller than 4032 bytes ({}). If this is unintentional, try padding the end of the file with zeros.", ipl3.len());
+    let primary = args.output.clone().unwrap_or_else(|| args.elf().with_extension(formats[0].extension()));
+    let mut outputs = vec![(formats[0], primary.clone())];
+    for &format in &formats[1..] {
+        let path = primary.with_extension(format.extension());
+        if let Some((other, _)) = outputs.iter().find(|(_, other)| *other == path) {
+            error!("ROM formats {} and {} would both be written to: {path}", other.extension(), format.extension());
+            std::process::exit(1);
+        }
+        outputs.push((format, path));
     }
     
-    let elf = Elf::new(elf_path).expect("failed to parse ELF");
+    outputs
+}
+
+/// Writes the ROM in the provided byte order, creating parent directories if needed.
+fn write_rom(rom: &Rom, format: ByteOrder, path: &Utf8Path) {
+    if let Some(parent) = path.parent().filter(|parent| !parent.as_str().is_empty()) {
+        fs::create_dir_all(parent).unwrap_or_else(|_| panic!("failed to create output directory: {parent}"));
+    }
+    let mut writer = BufWriter::new(File::create(path).unwrap_or_else(|_| panic!("failed to create ROM file: {path}")));
+    rom.write_to_with_order(&mut writer, format).and_then(|_| writer.flush()).expect("failed to write ROM");
+    info!("Generated ROM at: {}", path.canonicalize_utf8().unwrap_or_else(|_| path.to_path_buf()));
+}
+
+/// Logs which CIC variant the ROM's IPL3 was detected as, since the checksum of a ROM with an
+/// unknown IPL3 is zero, which is otherwise invisible until the ROM fails to boot.
+fn log_cic(rom: &Rom) {
+    if rom.ipl3.len() > 0xFC0 {
+        info!("IPL3: libdragon (loads the ELF file itself)");
+        return;
+    }
     
-    let mut rom = Rom::new(&elf, &ipl3, args.name, args.sections);
+    match (CicVariant::detect(&rom.ipl3), CicVariant::detect_checksum(&rom.ipl3)) {
+        (CicVariant::Unknown, CicVariant::Unknown) => info!("IPL3: custom (unknown CIC), so the checksum is zeroed"),
+        (CicVariant::Unknown, checksum) => info!("IPL3: custom, using the CIC-{checksum} checksum"),
+        (cic, _) => info!("IPL3: CIC-{cic}"),
+    }
+}
+
+/// Prints whether the header checksum of an existing ROM is valid.
+fn verify(rom_path: &Utf8Path) {
+    let (rom, _) = read_rom(rom_path);
     
-    let data = &mut rom.binary;
-    for append in args.appends {
-        if append.is_file() {
-            data.extend_from_slice(&fs::read(append).unwrap());
-        }
+    match rom.verify_checksum() {
+        Some(true) => println!("Checksum is valid: {:#018X}", rom.header.checksum),
+        Some(false) => {
+            println!("Checksum is invalid: {:#018X}", rom.header.checksum);
+            std::process::exit(1);
+        },
+        None => println!("Unable to verify checksum: ROM uses an unknown IPL3"),
+    }
+}
+
+/// Prints the differences between two existing ROMs, exiting with an error if they differ.
+fn diff(a: &Utf8Path, b: &Utf8Path) {
+    let diff = read_rom(a).0.diff(&read_rom(b).0);
+    println!("{diff}");
+    if !diff.is_empty() {
+        std::process::exit(1);
+    }
+}
+
+/// Recalculates the header checksum of an existing ROM (e.g. after patching it), and writes it to
+/// `output`, or back to `rom_path`, in the ROM's original byte order.
+fn rechecksum(rom_path: &Utf8Path, output: Option<&Utf8Path>) {
+    let (mut rom, order) = read_rom(rom_path);
+    
+    if CicVariant::detect_checksum(&rom.ipl3) == CicVariant::Unknown {
+        warn!("ROM uses an unknown IPL3, so the checksum will be set to zero.");
     }
+    let old_checksum = rom.header.checksum;
+    rom.update_checksum();
     
-    rom
+    let output = output.unwrap_or(rom_path);
+    fs::write(output, rom.to_vec_with_order(order)).unwrap_or_else(|_| panic!("failed to write ROM: {output}"));
+    info!("Updated checksum from {old_checksum:#018X} to {:#018X} in: {output}", rom.header.checksum);
 }
 
-fn from_libdragon_ipl3(args: Args) -> Rom {
-    let elf = Elf::new(&args.elf).expect("failed to parse ELF");
+/// Reads an existing ROM in any byte order, returning it along with the detected byte order. ROMs
+/// whose byte order can't be detected are assumed to be big-endian (.z64).
+fn read_rom(path: &Utf8Path) -> (Rom, ByteOrder) {
+    let mut data = fs::read(path).unwrap_or_else(|_| panic!("failed to read ROM: {path}"));
+    let order = ByteOrder::detect(&data).unwrap_or_else(|| {
+        warn!("Unable to detect the byte order of {path}, so it's assumed to be big-endian (.z64)");
+        ByteOrder::Z64
+    });
+    order.convert(&mut data);
     
-    use LibdragonIpl3Version::*;
-    let build = args.libdragon.unwrap_or(Release);
-    if build == Compat {
-        let mut rom = Rom::new(&elf, &LIBDRAGON_IPL3_COMPAT[0x40..], args.name, args.sections);
-        
-        let data = &mut rom.binary;
-        for append in args.appends {
-            if append.is_file() {
-                data.extend_from_slice(&fs::read(append).unwrap());
-            }
+    let rom = Rom::from_vec(&data).unwrap_or_else(|err| panic!("failed to parse ROM {path}: {err:?}"));
+    (rom, order)
+}
+
+/// Compares the generated ROM (in the provided byte order) to an existing ROM, exiting with an
+/// error at the first difference.
+fn expect_rom(rom: &Rom, format: ByteOrder, path: &Utf8Path) {
+    let expected = fs::read(path).unwrap_or_else(|_| panic!("failed to read expected ROM: {path}"));
+    let actual = rom.to_vec_with_order(format);
+    
+    let difference = actual.iter().zip(&expected).position(|(actual, expected)| actual != expected);
+    if let Some(offset) = difference {
+        let (region, start) = match offset {
+            offset if offset < 0x40 => ("header", 0),
+            offset if offset < rom.binary_offset() => ("IPL3", 0x40),
+            _ => ("binary", rom.binary_offset()),
+        };
+        error!("ROM differs from {path} at offset {offset:#X} ({region} offset {:#X}): expected {:#04X}, found {:#04X}", offset - start, expected[offset], actual[offset]);
+        std::process::exit(1);
+    }
+    if actual.len() != expected.len() {
+        error!("ROM is {:#X} bytes, but {path} is {:#X} bytes", actual.len(), expected.len());
+        std::process::exit(1);
+    }
+    
+    info!("ROM matches: {path}");
+}
+
+/// Prints what would have been written for a dry run.
+fn print_summary(rom: &Rom, layout: &[SectionPlacement], rom_path: &Utf8Path) {
+    let info = rom.info();
+    println!("Dry run, ROM not written to: {rom_path}");
+    println!("Size:  {:#X} bytes", rom.total_size());
+    println!("CIC:   {}", info.cic);
+    println!("Name:  {}", info.name);
+    println!();
+    print_map(rom, layout);
+}
+
+/// Prints a table of where each ELF section was placed in the ROM, followed by the end padding.
+fn print_map(rom: &Rom, layout: &[SectionPlacement]) {
+    if layout.is_empty() {
+        println!("No ELF sections were placed in the ROM (libdragon's debug/release IPL3 loads the entire ELF file instead)");
+        return;
+    }
+    
+    println!("{:<20} {:>10} {:>18} {:>10} {:>10}", "Section", "ROM offset", "Address", "Size", "Padding");
+    for section in layout {
+        println!("{:<20} {:>#10X} {:>#18X} {:>#10X} {:>#10X}", section.name, section.rom_offset, section.vaddr, section.len, section.padding_before);
+    }
+    
+    let end = layout.last().map(|section| section.rom_offset + section.len).unwrap_or_default();
+    println!("{:<20} {:>#10X} {:>18} {:>#10X}", "(end padding)", end, "", rom.total_size().saturating_sub(end));
+}
+
+/// Writes the ELF's symbols to a file, one per line, in a format similar to `nm`.
+/// 
+/// Functions are marked with `T`, data with `D`, and anything else with `?`. Local symbols use the
+/// lowercase version of the marker.
+fn dump_symbols(elf: &Elf, path: &Utf8Path) {
+    let mut symbols = elf.symbol_table().expect("failed to parse ELF symbols");
+    symbols.sort_by_key(|symbol| symbol.addr);
+    
+    let mut lines = String::new();
+    for symbol in symbols {
+        let marker = match symbol.kind {
+            SymbolKind::Text | SymbolKind::Label => 'T',
+            SymbolKind::Data | SymbolKind::Tls => 'D',
+            SymbolKind::Unknown => '?',
+            _ => continue,
+        };
+        let marker = if symbol.global { marker } else { marker.to_ascii_lowercase() };
+        lines.push_str(&format!("{:08X} {marker} {}\n", symbol.addr, symbol.name));
+    }
+    
+    fs::write(path, lines).unwrap_or_else(|_| panic!("failed to write symbols: {path}"));
+}
+
+/// Writes a JSON array describing where each appended file was placed in the ROM.
+fn write_append_manifest(appended: &[(&Utf8PathBuf, usize, usize)], path: &Utf8Path) {
+    let entries = appended.iter()
+        .map(|(file, offset, len)| format!("  {{ \"path\": {}, \"offset\": {offset}, \"len\": {len} }}", json_string(file.as_str())))
+        .collect::<Vec<_>>();
+    let json = format!("[\n{}\n]\n", entries.join(",\n"));
+    
+    fs::write(path, json).unwrap_or_else(|_| panic!("failed to write append manifest: {path}"));
+}
+
+/// Reads every file in `dir` and its subdirectories, named by their path relative to `root` (using
+/// `/` as the separator).
+fn read_filesystem(root: &Utf8Path, dir: &Utf8Path, entries: &mut Vec<(String, Vec<u8>)>) {
+    for entry in dir.read_dir_utf8().unwrap_or_else(|_| panic!("failed to read filesystem directory: {dir}")) {
+        let path = entry.unwrap_or_else(|_| panic!("failed to read filesystem directory: {dir}")).into_path();
+        if path.is_dir() {
+            read_filesystem(root, &path, entries);
+        } else {
+            let name = path.strip_prefix(root).unwrap().components().map(|c| c.as_str()).collect::<Vec<_>>().join("/");
+            let data = fs::read(&path).unwrap_or_else(|_| panic!("failed to read filesystem file: {path}"));
+            entries.push((name, data));
         }
-        
-        rom
-    } else {
-        let libdragon = match build {
-            Debug => LIBDRAGON_IPL3_DEV.to_vec(),
-            Release => LIBDRAGON_IPL3_PROD.to_vec(),
-            Path(path) => std::fs::read(path).expect("failed to read libdragon IPL3 file"),
-            Compat => unreachable!(),
+    }
+}
+
+/// Quotes and escapes a string for use in JSON.
+fn json_string(s: &str) -> String {
+    let mut quoted = String::from('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Loads the ELF file, warning if its entrypoint will be truncated in the ROM header.
+/// 
+/// With `--strip`, only the data of sections which may be included in the ROM is copied. This
+/// isn't known before parsing with `--auto-sections`, so every section is copied instead.
+fn load_elf(args: &Args) -> Elf {
+    let elf = if args.strip && !args.auto_sections {
+        let keep = match args.sections.is_empty() {
+            true => DEFAULT_SECTIONS.to_vec(),
+            false => args.sections.iter().map(|section| section.as_str()).collect(),
         };
-        
-        let mut binary = fs::read(&args.elf).unwrap();
-        let mut header = Header::new(libdragon[..0x40].try_into().unwrap());
-        header.pc = elf.entry;
-        
-        let mut name = args.name.unwrap_or_else(|| args.elf.file_name().unwrap().to_string()).as_bytes().to_vec();
-        name.resize(20, ' ' as u8);
-        header.image_name = name.try_into().unwrap();
-        
-        let misalignment = 256 - (libdragon.len() % 256);
-        if misalignment > 0 {
-            let mut aligned = vec![0x00; misalignment];
-            aligned.extend_from_slice(&binary);
-            
-            binary = aligned;
+        Elf::new_filtered(args.elf(), &keep)
+    } else {
+        Elf::new(args.elf())
+    }.expect("failed to parse ELF");
+    if !elf.entry_fits_u32() {
+        warn!("ELF entrypoint ({:#018X}) does not fit in 32 bits. The ROM header will use {:#010X} instead.", elf.entry, elf.entry_u32());
+    }
+    
+    elf
+}
+
+/// Generates a ROM from the ELF's sections, using the section/padding options from `args`.
+fn build_rom(elf: &Elf, ipl3: &[u8], args: &Args) -> (Rom, Vec<SectionPlacement>) {
+    if args.auto_sections && !args.sections.is_empty() {
+        warn!("--section is ignored, because --auto-sections includes every allocated section.");
+    }
+    let unmatched = match args.auto_sections {
+        true => vec![],
+        false => elf.unmatched_sections(&args.sections),
+    };
+    if !unmatched.is_empty() {
+        let unmatched = unmatched.join(", ");
+        if args.strict {
+            error!("No ELF sections match: {unmatched}");
+            std::process::exit(1);
         }
+        warn!("No ELF sections match: {unmatched}");
+    }
+    
+    let mut builder = RomBuilder::new(elf, ipl3)
+        .sections(args.sections.clone())
+        .auto_sections(args.auto_sections)
+        .strict_entry(args.strict)
+        .preserve_order(args.preserve_order)
+        .min_pad(!args.no_min_pad);
+    if let Some(name) = &args.name {
+        builder = builder.name(name);
+    }
+    if let Some(fill) = args.fill {
+        builder = builder.fill(fill);
+    }
+    if let Some(base_section) = &args.base_section {
+        builder = builder.base_section(base_section);
+    }
+    for placement in &args.placements {
+        builder = builder.place(&placement.name, placement.offset as usize);
+    }
+    for section in &args.section_data {
+        let data = fs::read(&section.path).unwrap_or_else(|_| panic!("failed to read section data: {}", section.path));
+        builder = builder.inject_section(ElfSection::from_data(&section.name, section.addr, data));
+    }
+    
+    builder.build_with_layout().expect("failed to generate ROM")
+}
+
+fn from_custom_ipl3<P: AsRef<Utf8Path>>(ipl3_path: P, elf: &Elf, args: &Args) -> (Rom, Vec<SectionPlacement>) {
+    let ipl3_path = ipl3_path.as_ref();
+    
+    let ipl3 = fs::read(ipl3_path).unwrap_or_else(|_| panic!("IPL3 does not exist: {ipl3_path}"));
+    let ipl3 = validate_ipl3(&ipl3).unwrap_or_else(|_| panic!("Provided IPL3 is larger than 4032 bytes ({}). For IPL3s which load the ELF file themselves, use `--libdragon <PATH>` instead.", ipl3.len()));
+    
+    debug!("Using IPL3: {ipl3_path}");
+    build_rom(elf, &ipl3, args)
+}
+
+/// Generates a ROM using the IPL3 from an existing ROM.
+fn from_rom_ipl3(rom_path: &Utf8Path, elf: &Elf, args: &Args) -> (Rom, Vec<SectionPlacement>) {
+    let data = fs::read(rom_path).unwrap_or_else(|_| panic!("failed to read ROM: {rom_path}"));
+    let ipl3 = from_rom(&data).unwrap_or_else(|_| panic!("Provided ROM is smaller than 4096 bytes ({}), so it can't contain an IPL3.", data.len()));
+    
+    debug!("Using IPL3 from ROM: {rom_path}");
+    build_rom(elf, &ipl3, args)
+}
+
+/// Generates a ROM using a libdragon IPL3.
+/// 
+/// The compat build boots like any other IPL3, so it's used with the ELF's sections. The debug,
+/// release, and custom builds instead load the entire ELF file from the ROM themselves, so they
+/// can't use the ELF's sections without breaking the boot process. Options which only apply to
+/// the sections are ignored with a warning.
+fn from_libdragon_ipl3(elf: &Elf, args: &Args) -> (Rom, Vec<SectionPlacement>) {
+    let libdragon = args.libdragon.as_ref().unwrap_or(&LibdragonIpl3Arg::Bundled(LibdragonIpl3::Release));
+    debug!("Using libdragon IPL3: {libdragon:?}");
+    // the compat build is built like any other IPL3, so every section option still applies
+    if let LibdragonIpl3Arg::Bundled(LibdragonIpl3::Compat) = libdragon {
+        return build_rom(elf, &LibdragonIpl3::Compat.data()[0x40..], args);
+    }
+    
+    if !args.sections.is_empty() || args.auto_sections || args.base_section.is_some() || !args.placements.is_empty() || !args.section_data.is_empty() || args.preserve_order {
+        warn!("--section, --auto-sections, --base-section, --place, --section-data, and --preserve-order are ignored, because this libdragon IPL3 loads the entire ELF file. Use `--libdragon compat` to only include specific sections.");
+    }
+    if args.fill.is_some() {
+        warn!("--fill is ignored, because this libdragon IPL3 loads the entire ELF file. Use `--pad-to` to pad the ROM instead.");
+    }
+    
+    let rom = match libdragon {
+        LibdragonIpl3Arg::Bundled(version) => Rom::with_libdragon(elf, *version, args.name.clone(), vec![]),
+        LibdragonIpl3Arg::Path(path) => {
+            let data = fs::read(path).expect("failed to read libdragon IPL3 file");
+            Rom::with_libdragon_ipl3(elf, &data, args.name.clone())
+        },
+    };
+    (rom.expect("failed to generate ROM"), vec![])
+}
+
+/// Replaces `>>ROM<<`, `$ELF`, `$ROM_DIR`, `$NAME`, `${VAR}` (an environment variable), and `$$` in
+/// an exec command, before it's split into arguments. Any other `$` is left as-is.
+fn expand_exec(cmd_str: &str, args: &Args, rom_path: &Utf8Path, name: &str) -> String {
+    let cmd_str = cmd_str.replace(">>ROM<<", rom_path.as_str());
+    let mut expanded = String::with_capacity(cmd_str.len());
+    let mut rest = cmd_str.as_str();
+    while let Some(start) = rest.find('$') {
+        expanded.push_str(&rest[..start]);
+        rest = &rest[start + 1..];
         
-        Rom {
-            header,
-            ipl3: libdragon[0x40..].to_vec(),
-            binary,
+        if let Some(after) = rest.strip_prefix('$') {
+            expanded.push('$');
+            rest = after;
+        } else if let Some((var, after)) = rest.strip_prefix('{').and_then(|var| var.split_once('}')) {
+            match std::env::var(var) {
+                Ok(value) => expanded.push_str(&value),
+                Err(_) if args.strict => {
+                    error!("Environment variable `{var}` is not set: {cmd_str}");
+                    std::process::exit(1);
+                },
+                Err(_) => warn!("Environment variable `{var}` is not set, so it was replaced with nothing: {cmd_str}"),
+            }
+            rest = after;
+        } else {
+            let len = rest.find(|c: char| !c.is_ascii_alphanumeric() && c != '_').unwrap_or(rest.len());
+            match &rest[..len] {
+                "ELF" => expanded.push_str(args.elf().as_str()),
+                "ROM_DIR" => expanded.push_str(rom_path.parent().map(|dir| dir.as_str()).filter(|dir| !dir.is_empty()).unwrap_or(".")),
+                "NAME" => expanded.push_str(name),
+                _ => {
+                    expanded.push('$');
+                    continue;
+                },
+            }
+            rest = &rest[len..];
         }
     }
+    expanded.push_str(rest);
+    
+    expanded
 }
 
-fn exec(cmd_str: &str) {
+/// Runs a command and waits for it to finish, returning its exit status. Returns `None` without
+/// running anything if the command is empty or can't be split into arguments.
+fn exec(cmd_str: &str) -> Option<ExitStatus> {
     let mut lex = Shlex::new(cmd_str);
     let args = lex.by_ref().collect::<Vec<_>>();
-    if args.is_empty() || lex.had_error { return; }
+    if args.is_empty() || lex.had_error { return None; }
     
-    Command::new(&args[0])
+    let status = Command::new(&args[0])
         .args(&args[1..])
         .spawn()
-        .expect(&format!("failed to start exec: {cmd_str}"))
+        .unwrap_or_else(|_| panic!("failed to start exec: {cmd_str}"))
         .wait()
-        .expect(&format!("failed to wait for exec: {cmd_str}"));
-}
\ No newline at end of file
+        .unwrap_or_else(|_| panic!("failed to wait for exec: {cmd_str}"));
+    
+    Some(status)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn parse_args(args: &[&str]) -> Args {
+        match cli().run_inner(bpaf::Args::from(args)) {
+            Ok(Cli::Build(args)) => args,
+            Ok(cli) => panic!("expected build arguments, found {cli:?}"),
+            Err(err) => panic!("failed to parse {args:?}: {err:?}"),
+        }
+    }
+    
+    #[test]
+    fn output_path() {
+        let args = parse_args(&["--elf", "target/game.elf", "-o", "dist/foo.z64"]);
+        assert_eq!(args.output.as_deref(), Some(Utf8Path::new("dist/foo.z64")));
+        
+        let outputs = output_paths(&args);
+        assert_eq!(outputs, [(ByteOrder::Z64, Utf8PathBuf::from("dist/foo.z64"))]);
+        assert_eq!(expand_exec("ares >>ROM<<", &args, &outputs[0].1, "game"), "ares dist/foo.z64");
+    }
+    
+    #[test]
+    fn default_output_path() {
+        let args = parse_args(&["--elf", "target/game.elf"]);
+        assert_eq!(args.output, None);
+        assert_eq!(output_paths(&args), [(ByteOrder::Z64, Utf8PathBuf::from("target/game.z64"))]);
+    }
+    
+    #[test]
+    fn fill_byte() {
+        assert_eq!(parse_args(&["--elf", "a.elf", "--fill", "00"]).fill, Some(0x00));
+        assert_eq!(parse_args(&["--elf", "a.elf", "--fill", "0xA5"]).fill, Some(0xA5));
+        assert_eq!(parse_args(&["--elf", "a.elf"]).fill, None);
+        assert!(parse_byte("100".into()).is_err());
+        assert!(parse_byte("zz".into()).is_err());
+    }
+    
+    #[test]
+    fn header_flags() {
+        let args = parse_args(&["--elf", "a.elf", "--country", "J", "--cart-id", "ZL", "--media-format", "N", "--revision", "3"]);
+        assert_eq!(args.country, Some(Country::Japan));
+        assert_eq!(args.cart_id, Some(u16::from_be_bytes(*b"ZL")));
+        assert_eq!(args.media_format, Some(b'N'));
+        assert_eq!(args.revision, Some(3));
+        
+        let args = parse_args(&["--elf", "a.elf"]);
+        assert_eq!((args.country, args.cart_id, args.media_format, args.revision), (None, None, None, None));
+    }
+    
+    #[test]
+    fn header_flag_validation() {
+        assert!(parse_cart_id("Z".into()).is_err());
+        assert!(parse_cart_id("ZLD".into()).is_err());
+        assert!(parse_cart_id("".into()).is_err());
+        assert!(parse_cart_id("é".into()).is_err());
+        assert_eq!(parse_cart_id("64".into()), Ok(0x3634));
+        
+        assert!(parse_ascii_char("".into()).is_err());
+        assert!(parse_ascii_char("ND".into()).is_err());
+        assert!(parse_ascii_char("é".into()).is_err());
+        assert_eq!(parse_ascii_char("D".into()), Ok(b'D'));
+        
+        assert!(cli().run_inner(bpaf::Args::from(&["--elf", "a.elf", "--revision", "256"])).is_err());
+    }
+    
+    #[test]
+    fn elf_can_come_from_config() {
+        let path = std::env::temp_dir().join("nust64-elf-can-come-from-config.toml");
+        fs::write(&path, "elf = \"config.elf\"\nname = \"config\"").unwrap();
+        let mut args = parse_args(&["--config", path.to_str().unwrap(), "-n", "cli"]);
+        merge_config(&mut args).unwrap();
+        fs::remove_file(&path).unwrap();
+        
+        assert_eq!(args.elf(), "config.elf");
+        assert_eq!(args.name.as_deref(), Some("cli"));
+    }
+    
+    #[test]
+    fn elf_is_required() {
+        assert!(merge_config(&mut parse_args(&["-n", "cli"])).is_err());
+        assert!(merge_config(&mut parse_args(&["--config", "missing.toml", "--elf", "a.elf"])).is_err());
+        assert!(matches!(cli().run_inner(bpaf::Args::from(&["--verify", "a.z64"])), Ok(Cli::Verify { .. })));
+    }
+    
+    #[test]
+    fn pad_to_sizes() {
+        assert_eq!(parse_size("8M".into()), Ok(0x800000));
+        assert_eq!(parse_size("16777216".into()), Ok(0x1000000));
+        assert_eq!(parse_size("0x1000000".into()), Ok(0x1000000));
+        assert_eq!(parse_size("512k".into()), Ok(0x80000));
+        assert!(parse_size("8G".into()).is_err());
+        assert!(parse_size("0xFFFFFFFFFFFFM".into()).is_err());
+        assert!(parse_size("0xFFFFFFFFFFFFFFFFK".into()).is_err());
+        assert_eq!(parse_args(&["--elf", "a.elf", "--pad-to", "8MB"]).pad_to, Some(0x800000));
+    }
+}
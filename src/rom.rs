@@ -1,18 +1,341 @@
+use std::fmt::{Display, Formatter};
+use std::io;
+use std::io::Write;
+use std::iter;
 use std::num::Wrapping;
+use std::ops::Range;
+use std::path::Path;
+use std::str::FromStr;
 use bytes::{Buf, BufMut, Bytes, BytesMut};
+use camino::Utf8Path;
 use crc::{Crc, CRC_32_ISO_HDLC};
-use crate::elf::Elf;
+use object::SectionKind;
+use crate::elf::{Elf, ElfSection};
+use crate::ipl3::LibdragonIpl3;
+use crate::{Error, Result};
 
 /// Used to determine IPL3 variant
 pub const CRC: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
 
+/// ELF sections included in the ROM when none are specified.
+pub const DEFAULT_SECTIONS: &[&str] = &[".boot", ".text", ".rodata", ".data", ".assets", ".bss"];
+
+/// Byte order of a ROM image file.
+/// 
+/// N64 ROMs are natively big-endian, but some older flash carts and emulators expect other layouts.
+/// The order applies to the _entire_ image, including the header and IPL3. As a result, the first
+/// word of the header (`pi_regs`) doubles as a byte order marker: `80 37 12 40` for .z64,
+/// `37 80 40 12` for .v64, and `40 12 37 80` for .n64.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum ByteOrder {
+    /// Big-endian (native), typically using the `.z64` extension.
+    #[default]
+    Z64,
+    /// 16-bit byte-swapped, typically using the `.v64` extension.
+    V64,
+    /// 32-bit little-endian, typically using the `.n64` extension.
+    N64,
+}
+impl ByteOrder {
+    /// File extension commonly used for this byte order (without the leading dot).
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Z64 => "z64",
+            Self::V64 => "v64",
+            Self::N64 => "n64",
+        }
+    }
+    
+    /// Detects the byte order of a ROM image from its first word (see [`ByteOrder`]), or `None` if
+    /// it isn't one of the standard markers.
+    pub fn detect(data: &[u8]) -> Option<Self> {
+        let marker = data.get(..4)?;
+        [Self::Z64, Self::V64, Self::N64].into_iter().find(|order| {
+            let mut expected = 0x80371240u32.to_be_bytes();
+            order.convert(&mut expected);
+            expected == marker
+        })
+    }
+    
+    /// Converts big-endian `data` into this byte order, in place.
+    /// 
+    /// Each conversion is its own inverse, so this can also be used to convert data from this byte
+    /// order _back_ into big-endian. Any trailing bytes that don't fill a complete 16-bit (.v64)
+    /// or 32-bit (.n64) unit are left untouched.
+    pub fn convert(&self, data: &mut [u8]) {
+        match self {
+            Self::Z64 => (),
+            Self::V64 => data.chunks_exact_mut(2).for_each(|chunk| chunk.swap(0, 1)),
+            Self::N64 => data.chunks_exact_mut(4).for_each(|chunk| chunk.reverse()),
+        }
+    }
+}
+impl FromStr for ByteOrder {
+    type Err = String;
+    
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Ok(match s.to_lowercase().as_str() {
+            "z64" => Self::Z64,
+            "v64" => Self::V64,
+            "n64" => Self::N64,
+            _ => return Err("Unable to parse ROM format. Expected: z64, v64, or n64".into()),
+        })
+    }
+}
+
+/// Kind of media a ROM image is intended for.
+/// 
+/// Support for 64DD disk images is currently minimal: they use the same layout as cartridge ROMs,
+/// marked by the `D` media format in the header, but the cartridge checksum is never applied
+/// since it's meaningless for disks.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum RomKind {
+    #[default]
+    Cartridge,
+    Disk64DD,
+}
+impl RomKind {
+    /// Header media format byte used to mark 64DD disk images.
+    pub const MEDIA_FORMAT_64DD: u8 = b'D';
+}
+
+/// Known CIC variants, identified by the IPL3 they're paired with.
+/// 
+/// The CIC variant also determines which checksum algorithm is used for the ROM header.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CicVariant {
+    /// Also used by the 7102.
+    Cic6101,
+    /// Also used by the 7101.
+    Cic6102,
+    /// Also used by the 7103.
+    Cic6103,
+    /// Also used by the 7105.
+    Cic6105,
+    /// Also used by the 7106.
+    Cic6106,
+    /// The IPL3 doesn't match any known official IPL3 (e.g. a custom or libdragon IPL3).
+    Unknown,
+}
+impl CicVariant {
+    /// [`CRC`] of the 6101 IPL3.
+    pub const CRC_6101: u32 = 0x6170A4A1;
+    /// [`CRC`] of the 6102/7101 IPL3.
+    pub const CRC_6102: u32 = 0x90BB6CB5;
+    /// [`CRC`] of the 7102 IPL3.
+    pub const CRC_7102: u32 = 0x009E9EA3;
+    /// [`CRC`] of the 6103/7103 IPL3.
+    pub const CRC_6103: u32 = 0x0B050EE0;
+    /// [`CRC`] of the 6105/7105 IPL3.
+    pub const CRC_6105: u32 = 0x98BC2C86;
+    /// [`CRC`] of the 6106/7106 IPL3.
+    pub const CRC_6106: u32 = 0xACC8580A;
+    /// [`CRC`] of the bundled compat build of libdragon's IPL3
+    /// ([`LIBDRAGON_IPL3_COMPAT`](crate::ipl3::LIBDRAGON_IPL3_COMPAT)), excluding its ROM header.
+    pub const CRC_LIBDRAGON_COMPAT: u32 = 0xAABEB54E;
+    
+    /// Determines the CIC variant matching the provided IPL3 (excluding the ROM header), based on
+    /// its [`CRC`].
+    pub fn detect(ipl3: &[u8]) -> Self {
+        match CRC.checksum(ipl3) {
+            Self::CRC_6101 | Self::CRC_7102 => Self::Cic6101,
+            Self::CRC_6102 => Self::Cic6102,
+            Self::CRC_6103 => Self::Cic6103,
+            Self::CRC_6105 => Self::Cic6105,
+            Self::CRC_6106 => Self::Cic6106,
+            _ => Self::Unknown,
+        }
+    }
+    
+    /// Determines the CIC variant whose checksum algorithm is used for the provided IPL3.
+    /// 
+    /// This is the same as [`Self::detect()`], except libdragon's compat IPL3 uses the 6102's
+    /// algorithm, since it's meant to boot like a standard IPL3 on 6102 cartridges. It's still
+    /// detected as [`Self::Unknown`], since it isn't an official IPL3.
+    pub fn detect_checksum(ipl3: &[u8]) -> Self {
+        match CRC.checksum(ipl3) {
+            Self::CRC_LIBDRAGON_COMPAT => Self::Cic6102,
+            _ => Self::detect(ipl3),
+        }
+    }
+    
+    /// Initial value of the checksum accumulators used by this variant, or `None` if unknown.
+    /// 
+    /// initial = (seed * magic_number) + 1
+    /// 
+    /// The seed is hardcoded into each CIC variant, and the magic number is hardcoded into the
+    /// matching IPL3 variant. However, even though 6101, 6102/7101, and 7102 are three different
+    /// variants, they use the same seed and magic number.
+    pub fn seed(&self) -> Option<u32> {
+        match self {
+            Self::Cic6101 | Self::Cic6102 => Some(((0x3Fu64 * 0x5D588B65u64) + 1) as u32),
+            Self::Cic6103 => Some(((0x78u64 * 0x6C078965u64) + 1) as u32),
+            Self::Cic6105 => Some(((0x91u64 * 0x5D588B65u64) + 1) as u32),
+            Self::Cic6106 => Some(((0x85u64 * 0x6C078965u64) + 1) as u32),
+            Self::Unknown => None,
+        }
+    }
+}
+
+impl Display for CicVariant {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Cic6101 => "6101/7102",
+            Self::Cic6102 => "6102/7101",
+            Self::Cic6103 => "6103/7103",
+            Self::Cic6105 => "6105/7105",
+            Self::Cic6106 => "6106/7106",
+            Self::Unknown => "unknown",
+        })
+    }
+}
+
+/// Region/country code stored in the ROM header.
+/// 
+/// Europe is encoded as `P`, `X`, or `Y` depending on the game's language, all of which are read
+/// as [`Country::Europe`]. [`Country::to_byte()`] always encodes it as `P`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Country {
+    Beta,
+    Asia,
+    Brazil,
+    China,
+    Germany,
+    Usa,
+    France,
+    Gateway64Ntsc,
+    Netherlands,
+    Italy,
+    Japan,
+    Korea,
+    Gateway64Pal,
+    Canada,
+    Europe,
+    Spain,
+    Australia,
+    Scandinavia,
+    Unknown(u8),
+}
+impl Country {
+    pub fn from_byte(byte: u8) -> Self {
+        match byte {
+            b'7' => Self::Beta,
+            b'A' => Self::Asia,
+            b'B' => Self::Brazil,
+            b'C' => Self::China,
+            b'D' => Self::Germany,
+            b'E' => Self::Usa,
+            b'F' => Self::France,
+            b'G' => Self::Gateway64Ntsc,
+            b'H' => Self::Netherlands,
+            b'I' => Self::Italy,
+            b'J' => Self::Japan,
+            b'K' => Self::Korea,
+            b'L' => Self::Gateway64Pal,
+            b'N' => Self::Canada,
+            b'P' | b'X' | b'Y' => Self::Europe,
+            b'S' => Self::Spain,
+            b'U' => Self::Australia,
+            b'W' => Self::Scandinavia,
+            byte => Self::Unknown(byte),
+        }
+    }
+    
+    pub fn to_byte(&self) -> u8 {
+        match self {
+            Self::Beta => b'7',
+            Self::Asia => b'A',
+            Self::Brazil => b'B',
+            Self::China => b'C',
+            Self::Germany => b'D',
+            Self::Usa => b'E',
+            Self::France => b'F',
+            Self::Gateway64Ntsc => b'G',
+            Self::Netherlands => b'H',
+            Self::Italy => b'I',
+            Self::Japan => b'J',
+            Self::Korea => b'K',
+            Self::Gateway64Pal => b'L',
+            Self::Canada => b'N',
+            Self::Europe => b'P',
+            Self::Spain => b'S',
+            Self::Australia => b'U',
+            Self::Scandinavia => b'W',
+            Self::Unknown(byte) => *byte,
+        }
+    }
+}
+impl Display for Country {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Beta => "Beta",
+            Self::Asia => "Asia",
+            Self::Brazil => "Brazil",
+            Self::China => "China",
+            Self::Germany => "Germany",
+            Self::Usa => "USA",
+            Self::France => "France",
+            Self::Gateway64Ntsc => "Gateway 64 (NTSC)",
+            Self::Netherlands => "Netherlands",
+            Self::Italy => "Italy",
+            Self::Japan => "Japan",
+            Self::Korea => "Korea",
+            Self::Gateway64Pal => "Gateway 64 (PAL)",
+            Self::Canada => "Canada",
+            Self::Europe => "Europe",
+            Self::Spain => "Spain",
+            Self::Australia => "Australia",
+            Self::Scandinavia => "Scandinavia",
+            Self::Unknown(byte) => return write!(f, "Unknown ({byte:#04X})"),
+        })
+    }
+}
+impl FromStr for Country {
+    type Err = String;
+    
+    /// Parses either a country code character (e.g. `E` or `e`), or a name (e.g. `usa`, `us`).
+    /// Code characters are case-insensitive, since every known code is uppercase.
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        if let [byte] = s.as_bytes() {
+            if byte.is_ascii() {
+                return Ok(Self::from_byte(byte.to_ascii_uppercase()));
+            }
+        }
+        
+        Ok(match s.to_lowercase().as_str() {
+            "beta" => Self::Beta,
+            "asia" => Self::Asia,
+            "brazil" | "br" => Self::Brazil,
+            "china" | "cn" => Self::China,
+            "germany" | "de" => Self::Germany,
+            "usa" | "us" => Self::Usa,
+            "france" | "fr" => Self::France,
+            "netherlands" | "nl" => Self::Netherlands,
+            "italy" | "it" => Self::Italy,
+            "japan" | "jp" => Self::Japan,
+            "korea" | "kr" => Self::Korea,
+            "canada" | "ca" => Self::Canada,
+            "europe" | "eu" => Self::Europe,
+            "spain" | "es" => Self::Spain,
+            "australia" | "au" => Self::Australia,
+            "scandinavia" => Self::Scandinavia,
+            _ => return Err(format!("Unable to parse country: {s}. Expected a country code character, or a name such as: usa, japan, or europe")),
+        })
+    }
+}
+
 /// Represents an N64 ROM header with all known header fields.
 #[derive(Copy, Clone, Debug, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Header {
     /// The first 4 bytes of the header are used by IPL2 to initialize the PI DOM1_xxx registers.
     /// Emulators often use them to determine the endianness of the ROM, but they can be different
     /// values than the standard found in all official game releases.
     pub pi_regs: u32,
+    /// Overrides the clock rate libultra reports, except for the lower 4 bits which are unused.
+    /// Every official game uses [`Self::CLOCKRATE_DEFAULT`], which keeps the console's clock rate.
+    /// See [`Self::set_clockrate()`].
     pub clockrate: u32,
     /// Also known as the entrypoint, however different IPL3 variants treat this value differently
     /// (e.g. some will offset it by some amount first.)
@@ -29,6 +352,9 @@ pub struct Header {
     pub revision: u8,
 }
 impl Header {
+    /// Clock rate which keeps the console's default, with only the unused lower 4 bits set.
+    pub const CLOCKRATE_DEFAULT: u32 = 0x0000000F;
+    
     /// Parses binary header data into a [`Header`]. 
     pub fn new(data: [u8; 0x40]) -> Self {
         let mut data = Bytes::from(data.to_vec());
@@ -60,43 +386,81 @@ impl Header {
         }
     }
     
+    /// Default image name for a ROM generated from the file at `path`, used when no name is given.
+    /// 
+    /// This is the file name without its extension (including `.elf` for compressed files, e.g.
+    /// `game.elf.gz`), with `-` and `_` replaced by spaces. For example, `target/game-debug.elf`
+    /// becomes `game debug`.
+    pub fn default_name<P: AsRef<Path>>(path: P) -> String {
+        let path = path.as_ref();
+        let stem = path.file_stem().unwrap_or(path.as_os_str()).to_string_lossy();
+        let stem = stem.strip_suffix(".elf").unwrap_or(&stem);
+        
+        stem.replace(['-', '_'], " ")
+    }
+    
     /// Generates a new [`Header`] using the binary part of a rom, an IPL3, name, and entrypoint.
     /// 
-    /// Use [`Self::new()`] to parse existing header data.
+    /// Use [`Self::new()`] to parse existing header data, or [`HeaderBuilder`] to change any of
+    /// the other default values.
     pub fn generate<S: AsRef<str>>(binary: &[u8], ipl3: &[u8], name: S, entry: u32) -> Self {
-        let mut combined = BytesMut::with_capacity(binary.len() + ipl3.len());
-        combined.extend_from_slice(binary);
-        combined.extend_from_slice(&ipl3);
-        
-        let mut name = name.as_ref().as_bytes().to_vec();
-        name.resize(20, ' ' as u8);
-        
-        let name: [u8; 20] = name.try_into().unwrap();
+        HeaderBuilder::new()
+            .name(name)
+            .entry(entry)
+            .build(binary, ipl3)
+    }
+    
+    /// Sets the image name, trimmed or padded with ASCII spaces to exactly 20 bytes.
+    /// 
+    /// Names are only trimmed at character boundaries, so a multibyte character which doesn't fit
+    /// is replaced by padding.
+    pub fn set_name<S: AsRef<str>>(&mut self, name: S) {
+        let name = name.as_ref();
+        let mut end = name.len().min(20);
+        while !name.is_char_boundary(end) {
+            end -= 1;
+        }
         
-        let mut check_ipl3 = ipl3.to_vec();
-        check_ipl3.resize(4032, 0x00);
-        let checksum = Self::calculate_checksum(binary, check_ipl3.try_into().unwrap());
+        self.image_name = [b' '; 20];
+        self.image_name[..end].copy_from_slice(&name.as_bytes()[..end]);
+    }
+    
+    /// Sets the clock rate, returning [`Error::InvalidClockRate`] if its lower 4 bits aren't all
+    /// set.
+    /// 
+    /// Those bits are unused, but are set in every official game, so a value without them is most
+    /// likely a mistake (e.g. a value in the wrong byte order, or a clock speed in MHz).
+    pub fn set_clockrate(&mut self, clockrate: u32) -> Result<()> {
+        if clockrate & 0xF != 0xF {
+            return Err(Error::InvalidClockRate(clockrate));
+        }
+        self.clockrate = clockrate;
         
-        Self {
-            pi_regs: 0x80371240,
-            clockrate: 0x0000000F,
-            pc: entry,
-            unknown0: 0x0000,
-            release: 0x1E4E, // who needs libultra when you have rust?
-            checksum,
-            unknown1: 0x0000000000000000,
-            image_name: name,
-            unknown2: [0x00; 7],
-            media_format: 0x52, // "R" (rust)
-            cart_id: 0x3634, // "64"
-            country: 0x37, // "7" (beta)
-            revision: 0x01
-        }
-    }
-    
-    /// Encodes the header data into a `Vec`.
+        Ok(())
+    }
+    
+    /// Country code of this header as a [`Country`].
+    pub fn country_enum(&self) -> Country {
+        Country::from_byte(self.country)
+    }
+    
+    /// Kind of media this header is for, based on its media format.
+    pub fn kind(&self) -> RomKind {
+        match self.media_format {
+            RomKind::MEDIA_FORMAT_64DD => RomKind::Disk64DD,
+            _ => RomKind::Cartridge,
+        }
+    }
+    
+    /// Encodes the header data into a `Vec`. See [`Self::to_bytes()`].
     pub fn to_vec(&self) -> Vec<u8> {
-        let mut data = BytesMut::with_capacity(0x40);
+        self.to_bytes().to_vec()
+    }
+    
+    /// Encodes the header data into exactly 0x40 bytes. This is the inverse of [`Self::new()`].
+    pub fn to_bytes(&self) -> [u8; 0x40] {
+        let mut bytes = [0x00; 0x40];
+        let mut data = &mut bytes[..];
         
         data.put_u32(self.pi_regs);
         data.put_u32(self.clockrate);
@@ -112,7 +476,9 @@ impl Header {
         data.put_u8(self.country);
         data.put_u8(self.revision);
         
-        data.to_vec()
+        // panics above if the fields are larger than 0x40 bytes, and this catches them being smaller
+        debug_assert!(data.is_empty(), "header fields must fill exactly 0x40 bytes");
+        bytes
     }
     
     /// Computes the 64-bit checksum found in N64 ROM headers.
@@ -121,46 +487,81 @@ impl Header {
     /// obscurity", like many checksum algorithms developed by Nintendo at the time.
     /// 
     /// The checksum depends on the IPL3 being used. Custom IPL3s will cause this function
-    /// to return a checksum of `0u64`. This may be changed in future versions.
+    /// to return a checksum of `0u64`. If a custom IPL3 still runs one of the standard checksum
+    /// algorithms, use [`Self::calculate_checksum_with_seed()`] instead.
+    /// 
+    /// libdragon's open-source IPL3s don't verify the header checksum, so a checksum of `0u64` is
+    /// expected for ROMs using them. The exception is the bundled compat build, which uses the
+    /// 6102's checksum (see [`CicVariant::detect_checksum()`]).
+    /// 
+    /// Only the first 1MB (0x100000 bytes) of `binary` is covered by the checksum. If `binary` is
+    /// shorter than that, the missing bytes are treated as zeros.
     /// 
     /// Original source: http://n64dev.org/n64crc.html
     pub fn calculate_checksum(binary: &[u8], ipl3: [u8; 0x1000 - 0x40]) -> u64 {
-        #[derive(PartialEq)]
-        enum Variant {
-            X103,
-            X105,
-            X106,
-            Others,
-        }
-        use Variant::*;
-        
-        // The initial value is decided based on which IPL3 variant is used
-        // initial = (seed * magic_number) + 1
-        //
-        // The seed is hardcoded into each CIC variant, and the magic number is hardcoded into the
-        // matching IPL3 variant. However, even though 6101, 6102/7101, and 7102 are three different
-        // variants, they use the same seed and magic number.
-        let (initial, variant) = match CRC.checksum(&ipl3) {
-            0x6170A4A1 | 0x90BB6CB5 | 0x009E9EA3 => (((0x3Fu64 * 0x5D588B65u64) + 1) as u32, Others), // 6101, 6102/7101, 7102
-            0x0B050EE0 => (((0x78u64 * 0x6C078965u64) + 1) as u32, X103), // 6103/7103
-            0x98BC2C86 => (((0x91u64 * 0x5D588B65u64) + 1) as u32, X105), // 6105/7105
-            0xACC8580A => (((0x85u64 * 0x6C078965u64) + 1) as u32, X106), // 6106/7106
-            _ => return 0
-        };
+        let (high, low) = Self::calculate_checksum_halves(binary, ipl3);
+        ((high as u64) << 32) | (low as u64)
+    }
+    
+    /// Same as [`Self::calculate_checksum()`], but returns the upper and lower 32-bit halves of the
+    /// checksum separately, as `(high, low)`. The CIC compares each half separately.
+    pub fn calculate_checksum_halves(binary: &[u8], ipl3: [u8; 0x1000 - 0x40]) -> (u32, u32) {
+        let variant = CicVariant::detect_checksum(&ipl3);
+        match variant.seed() {
+            Some(seed) => Self::checksum_halves(binary, ipl3, seed, variant),
+            None => (0, 0),
+        }
+    }
+    
+    /// Computes the 64-bit header checksum using a caller-provided seed, instead of detecting it
+    /// from the IPL3.
+    /// 
+    /// `seed` is the initial value of the checksum accumulators, calculated as
+    /// `(cic_seed * magic_number) + 1` (e.g. `(0x3F * 0x5D588B65) + 1` for the 6102). `variant`
+    /// selects how the accumulators are updated and combined into the final value. The 6101 and
+    /// [`CicVariant::Unknown`] both use the same algorithm as the 6102.
+    /// 
+    /// This is intended for custom IPL3s which still implement one of the standard checksum
+    /// algorithms, which [`Self::calculate_checksum()`] is unable to recognize.
+    /// 
+    /// Only the first 1MB (0x100000 bytes) of `binary` is covered by the checksum. If `binary` is
+    /// shorter than that, the missing bytes are treated as zeros.
+    pub fn calculate_checksum_with_seed(binary: &[u8], ipl3: [u8; 0x1000 - 0x40], seed: u32, variant: CicVariant) -> u64 {
+        let (high, low) = Self::checksum_halves(binary, ipl3, seed, variant);
+        ((high as u64) << 32) | (low as u64)
+    }
+    
+    /// The checksum has to be calculated sequentially. Most accumulators could be split across
+    /// threads and combined afterwards (sums, XORs, and the carry count in `t4`), but `t2` compares
+    /// its running value against each word to decide how it's updated, and `t1` depends on the
+    /// running value of `t5`. Splitting the data would produce different results, so
+    /// [`Rom::update_checksum()`] avoids recalculating the checksum when the first 1MB hasn't
+    /// changed instead.
+    fn checksum_halves(binary: &[u8], ipl3: [u8; 0x1000 - 0x40], seed: u32, variant: CicVariant) -> (u32, u32) {
+        use CicVariant::*;
         
-        let mut t1 = Wrapping(initial);
-        let mut t2 = Wrapping(initial);
-        let mut t3 = Wrapping(initial);
-        let mut t4 = Wrapping(initial);
-        let mut t5 = Wrapping(initial);
-        let mut t6 = Wrapping(initial);
+        let mut t1 = Wrapping(seed);
+        let mut t2 = Wrapping(seed);
+        let mut t3 = Wrapping(seed);
+        let mut t4 = Wrapping(seed);
+        let mut t5 = Wrapping(seed);
+        let mut t6 = Wrapping(seed);
         
-        let mut data = Bytes::from(binary[..0x100000].to_vec());
-        let mut table = Bytes::from(ipl3[0x710..0x750].to_vec());
+        // the 6105 mixes in a 256 byte table from its IPL3, cycling through it every 64 words
+        let table = &ipl3[0x710..0x810];
+        let table_words = table.len() / 4;
         
-        while data.has_remaining() {
-            let word = data.get_u32();
-            
+        // shorter binaries are treated as if they were padded with zeros
+        let words = binary[..binary.len().min(0x100000)].chunks(4)
+            .map(|chunk| {
+                let mut word = [0x00; 4];
+                word[..chunk.len()].copy_from_slice(chunk);
+                u32::from_be_bytes(word)
+            })
+            .chain(iter::repeat(0u32))
+            .take(0x100000 / 4);
+        
+        for (i, word) in words.enumerate() {
             let rot = Wrapping(word.rotate_left(word & 0x1F));
             let word = Wrapping(word);
             
@@ -178,24 +579,170 @@ impl Header {
                 t2 ^= t6 ^ word;
             }
             
-            if variant == X105 {
-                t1 += Wrapping(table.get_u32()) ^ word;
-                if !table.has_remaining() {
-                    table = Bytes::from(table.to_vec());
-                }
+            if variant == Cic6105 {
+                let offset = (i % table_words) * 4;
+                t1 += Wrapping(u32::from_be_bytes(table[offset..offset + 4].try_into().unwrap())) ^ word;
             } else {
                 t1 += t5 ^ word;
             }
         }
         
         match variant {
-            X103 => ((((t6 ^ t4) + t3).0 as u64) << 32) | (((t5 ^ t2) + t1).0 as u64),
-            X106 => ((((t6 * t4) + t3).0 as u64) << 32) | (((t5 * t2) + t1).0 as u64),
-            _ =>    ((((t6 ^ t4) ^ t3).0 as u64) << 32) | (((t5 ^ t2) ^ t1).0 as u64)
+            Cic6103 => (((t6 ^ t4) + t3).0, ((t5 ^ t2) + t1).0),
+            Cic6106 => (((t6 * t4) + t3).0, ((t5 * t2) + t1).0),
+            _ =>    (((t6 ^ t4) ^ t3).0, ((t5 ^ t2) ^ t1).0)
+        }
+    }
+}
+
+impl TryFrom<&[u8]> for Header {
+    type Error = Error;
+    
+    /// Parses the first 0x40 bytes of `data` using [`Header::new()`]. Any bytes past that are
+    /// ignored.
+    /// 
+    /// Returns [`Error::TruncatedHeader`] if `data` is shorter than 0x40 bytes.
+    fn try_from(data: &[u8]) -> Result<Self> {
+        match data.get(..0x40) {
+            Some(header) => Ok(Self::new(header.try_into().unwrap())),
+            None => Err(Error::TruncatedHeader(data.len())),
+        }
+    }
+}
+
+/// Builder for generating a [`Header`], with any fields not set using the same defaults as
+/// [`Header::generate()`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct HeaderBuilder {
+    header: Header,
+}
+impl Default for HeaderBuilder {
+    fn default() -> Self {
+        Self {
+            header: Header {
+                pi_regs: 0x80371240,
+                clockrate: Header::CLOCKRATE_DEFAULT,
+                pc: 0x80000400,
+                unknown0: 0x0000,
+                release: 0x1E4E, // who needs libultra when you have rust?
+                checksum: 0,
+                unknown1: 0x0000000000000000,
+                image_name: [b' '; 20],
+                unknown2: [0x00; 7],
+                media_format: 0x52, // "R" (rust)
+                cart_id: 0x3634, // "64"
+                country: 0x37, // "7" (beta)
+                revision: 0x01
+            },
+        }
+    }
+}
+impl HeaderBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Image name, trimmed or padded with ASCII spaces to exactly 20 bytes. See
+    /// [`Header::set_name()`].
+    pub fn name<S: AsRef<str>>(mut self, name: S) -> Self {
+        self.header.set_name(name);
+        self
+    }
+    
+    /// Entrypoint (`pc`) of the ROM.
+    pub fn entry(mut self, entry: u32) -> Self {
+        self.header.pc = entry;
+        self
+    }
+    
+    /// First word of the header (`pi_regs`), used by IPL2 to configure the PI's DOM1 timings for
+    /// reading the rest of the ROM. If not set, the standard `0x80371240` is used, which is what
+    /// every official game uses.
+    /// 
+    /// Most emulators and flash cart menus detect a ROM's byte order from this word (see
+    /// [`ByteOrder`]), so ROMs using another value may be loaded with the wrong byte order, or
+    /// rejected entirely.
+    pub fn pi_regs(mut self, pi_regs: u32) -> Self {
+        self.header.pi_regs = pi_regs;
+        self
+    }
+    
+    /// Clock rate (see [`Header::clockrate`]). If not set, [`Header::CLOCKRATE_DEFAULT`] is used.
+    /// Unlike [`Header::set_clockrate()`], the value isn't checked.
+    pub fn clockrate(mut self, clockrate: u32) -> Self {
+        self.header.clockrate = clockrate;
+        self
+    }
+    
+    pub fn country(mut self, country: u8) -> Self {
+        self.header.country = country;
+        self
+    }
+    
+    pub fn media_format(mut self, media_format: u8) -> Self {
+        self.header.media_format = media_format;
+        self
+    }
+    
+    pub fn cart_id(mut self, cart_id: u16) -> Self {
+        self.header.cart_id = cart_id;
+        self
+    }
+    
+    pub fn revision(mut self, revision: u8) -> Self {
+        self.header.revision = revision;
+        self
+    }
+    
+    /// Generates the header, computing its checksum from the binary part of a rom and an IPL3.
+    pub fn build(self, binary: &[u8], ipl3: &[u8]) -> Header {
+        let mut check_ipl3 = ipl3.to_vec();
+        check_ipl3.resize(4032, 0x00);
+        
+        Header {
+            checksum: Header::calculate_checksum(binary, check_ipl3.try_into().unwrap()),
+            ..self.header
         }
     }
 }
 
+/// Inputs and result of the last checksum calculated by [`Rom::update_checksum()`], so it can be
+/// reused while neither the IPL3 nor the first 1MB of the binary have changed.
+#[derive(Clone, Default)]
+struct ChecksumCache {
+    ipl3: Vec<u8>,
+    /// Copy of the checksummed region of the binary (at most the first 1MB).
+    binary: Vec<u8>,
+    checksum: u64,
+}
+impl ChecksumCache {
+    /// Returns the checksum of `binary` and `ipl3`, only recalculating it if either differs from
+    /// the last call.
+    fn checksum(&mut self, binary: &[u8], ipl3: [u8; 0x1000 - 0x40]) -> u64 {
+        let covered = &binary[..binary.len().min(0x100000)];
+        if self.ipl3 != ipl3 || self.binary != covered {
+            self.checksum = Header::calculate_checksum(covered, ipl3);
+            self.ipl3.clear();
+            self.ipl3.extend_from_slice(&ipl3);
+            self.binary.clear();
+            self.binary.extend_from_slice(covered);
+        }
+        
+        self.checksum
+    }
+}
+impl PartialEq for ChecksumCache {
+    /// The cache doesn't affect a ROM's contents, so it's ignored when comparing ROMs.
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+impl std::fmt::Debug for ChecksumCache {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ChecksumCache").field("checksum", &self.checksum).finish_non_exhaustive()
+    }
+}
+
 /// Represents an N64 ROM binary split into the parts: the header, IPL3, and remaining binary.
 #[derive(Clone, Debug, PartialEq)]
 pub struct Rom {
@@ -203,97 +750,1610 @@ pub struct Rom {
     /// Initial Program Load Stage 3, run during the boot process of the console.
     pub ipl3: Vec<u8>,
     /// The remaining binary code found after the IPL3 section.
+    /// 
+    /// Prefer [`Rom::edit_binary()`] for modifying it, which keeps the checksum up to date.
+    /// 
+    /// PI DMA transfers 2 bytes at a time, so the binary's length should be a multiple of 2 for
+    /// the ROM to work on hardware. This isn't enforced, since ROMs parsed with
+    /// [`Self::from_vec()`] or generated from ELFs using libdragon's debug/release IPL3s may have
+    /// any length, and appending or inserting data may leave it unaligned. Use
+    /// [`Self::align_binary()`] before writing the ROM if it may be unaligned.
     pub binary: Vec<u8>,
+    checksum_cache: ChecksumCache,
 }
 impl Rom {
     /// Extracts necessary data from an [`Elf`] to generate an N64-compatible ROM.
     /// 
     /// The ROM header will be auto-generated based on the Elf. If `name` is Some, it will be used
-    /// in the ROM's header. Otherwise the name of the Elf artifact will be used. In either case,
-    /// the name will be trimmed or padded with ASCII spaces to exactly 20 bytes. 
+    /// in the ROM's header. Otherwise a name based on the Elf artifact's file name will be used
+    /// (see [`Header::default_name()`]). In either case, the name will be trimmed or padded with
+    /// ASCII spaces to exactly 20 bytes.
     /// 
     /// By default, only the ELF sections .boot, .text, .rodata, .data, .assets, and .bss are
     /// included in the ROM. If `section_overrides` is not empty, the sections from the argument
-    /// will be used _instead of_ the default set.
+    /// will be used _instead of_ the default set. Included uninitialized sections (e.g. .bss) are
+    /// zero-filled to their declared size.
+    /// 
+    /// The binary is padded with `0xFF` to at least 1MB _before_ the checksum is calculated, so the
+    /// checksum of a small ROM covers its padding too.
+    /// 
+    /// For additional options, use a [`RomBuilder`] instead.
+    /// 
+    /// The ROM's layout starts at the lowest-addressed executable section that's included, which is
+    /// normally .boot.
     /// 
     /// # Panics
-    /// The ELF _must_ contain an executable .boot section. If using `section_overrides`, be sure to
-    /// include a `.boot` element.
+    /// At least one included section _must_ be executable. If using `section_overrides`, be sure
+    /// to include a `.boot` element. Use [`Self::try_new()`] to handle this case without panicking.
     pub fn new(elf: &Elf, ipl3: &[u8], name: Option<String>, section_overrides: Vec<String>) -> Self {
-        let mut binary = vec![];
-        let included_sections = if !section_overrides.is_empty() {
-            section_overrides
-        } else {
-            vec![".boot", ".text", ".rodata", ".data", ".assets", ".bss"]
-                .into_iter()
-                .map(|n| n.to_string())
-                .collect()
+        Self::try_new(elf, ipl3, name, section_overrides).unwrap()
+    }
+    
+    /// Same as [`Self::new()`], but returns an error instead of panicking.
+    /// 
+    /// Returns [`Error::MissingElfSection`] if the ELF doesn't contain a .boot section (or
+    /// [`Error::MissingElfSections`] if other included sections are missing too),
+    /// [`Error::NotExecutable`] if none of the included sections are executable,
+    /// [`Error::SectionBeforeBase`] if an included section starts before the section the layout
+    /// starts at, or [`Error::OverlappingSections`] if any included sections overlap.
+    /// 
+    /// A warning is logged if the ELF's entrypoint isn't within any included section. Use
+    /// [`RomBuilder::strict_entry()`] to return an error instead.
+    pub fn try_new(elf: &Elf, ipl3: &[u8], name: Option<String>, section_overrides: Vec<String>) -> Result<Self> {
+        let mut builder = RomBuilder::new(elf, ipl3).sections(section_overrides);
+        if let Some(name) = name {
+            builder = builder.name(name);
+        }
+        
+        builder.build()
+    }
+    
+    /// Generates a ROM using one of the bundled libdragon IPL3 builds.
+    /// 
+    /// The compat build is used the same way as [`Self::try_new()`]. The debug and release builds
+    /// load the ELF file themselves, so they're used with [`Self::with_libdragon_ipl3()`] instead,
+    /// and `sections` is ignored.
+    pub fn with_libdragon(elf: &Elf, version: LibdragonIpl3, name: Option<String>, sections: Vec<String>) -> Result<Self> {
+        match version {
+            LibdragonIpl3::Compat => Self::try_new(elf, &version.data()[0x40..], name, sections),
+            _ => Self::with_libdragon_ipl3(elf, version.data(), name),
+        }
+    }
+    
+    /// Generates a ROM using a libdragon IPL3 which loads the ELF file directly from the ROM, such
+    /// as the debug and release builds. The entire ELF file is placed after the IPL3, aligned to
+    /// 256 bytes.
+    /// 
+    /// `libdragon` must be the full IPL3 file, including the ROM header template in its first 0x40
+    /// bytes. Otherwise [`Error::InvalidIpl3Length`] is returned.
+    /// 
+    /// The header checksum is copied from the template (`0` for the bundled builds). These IPL3s
+    /// never verify it, and libdragon doesn't define a checksum of its own, so there's no
+    /// meaningful value to calculate.
+    pub fn with_libdragon_ipl3(elf: &Elf, libdragon: &[u8], name: Option<String>) -> Result<Self> {
+        if libdragon.len() <= 0x40 {
+            return Err(Error::InvalidIpl3Length(libdragon.len()));
+        }
+        
+        let mut header = Header::new(libdragon[..0x40].try_into().unwrap());
+        header.pc = elf.entry_u32();
+        
+        header.set_name(name.unwrap_or_else(|| Header::default_name(&elf.path)));
+        
+        // libdragon's IPL3 loads the ELF file itself, so the entire file is used as the binary
+        let misalignment = 256 - (libdragon.len() % 256);
+        let mut binary = vec![0x00; misalignment];
+        binary.extend_from_slice(elf.bytes());
+        
+        Ok(Self {
+            header,
+            ipl3: libdragon[0x40..].to_vec(),
+            binary,
+            checksum_cache: ChecksumCache::default(),
+        })
+    }
+    
+    /// Loads the ELF file at `path`, and generates a ROM using the bundled release build of
+    /// libdragon's IPL3, with the default name. This is the same as `Rom::from(&Elf::new(path)?)`.
+    /// 
+    /// Returns any error from loading the ELF file (see [`Elf::new()`]).
+    pub fn from_elf_path<P: AsRef<Utf8Path>>(path: P) -> Result<Self> {
+        let elf = Elf::new(path)?;
+        Self::with_libdragon(&elf, LibdragonIpl3::Release, None, vec![])
+    }
+    
+    /// Same as [`Self::try_new()`], but includes ELF sections based on their kind instead of their
+    /// name. See [`RomBuilder::section_kinds()`].
+    pub fn new_by_kind(elf: &Elf, ipl3: &[u8], name: Option<String>, kinds: &[SectionKind]) -> Result<Self> {
+        let mut builder = RomBuilder::new(elf, ipl3).section_kinds(kinds.to_vec());
+        if let Some(name) = name {
+            builder = builder.name(name);
+        }
+        
+        builder.build()
+    }
+    
+    /// Parses an existing N64 ROM image (big-endian/.z64) into its header, IPL3, and binary.
+    /// 
+    /// The first 0x40 bytes are parsed as the [`Header`], the next 0xFC0 bytes are used as the
+    /// IPL3, and everything after that becomes the binary. This is the inverse of [`Self::to_vec()`].
+    /// 
+    /// For any ROM whose IPL3 is exactly 0xFC0 bytes (including every ROM built by a
+    /// [`RomBuilder`] from an IPL3 checked by [`validate_ipl3()`](crate::ipl3::validate_ipl3)),
+    /// `Rom::from_vec(&rom.to_vec())` is equal to `rom`. ROMs using larger IPL3s (e.g. libdragon's
+    /// debug and release builds) can't be split back into the same parts, since the IPL3's length
+    /// isn't stored in the ROM.
+    /// 
+    /// Returns [`Error::RomTooSmall`] if `data` is shorter than 0x1000 bytes.
+    pub fn from_vec(data: &[u8]) -> Result<Self> {
+        if data.len() < 0x1000 {
+            return Err(Error::RomTooSmall(data.len()));
+        }
+        
+        Ok(Self {
+            header: Header::new(data[..0x40].try_into().unwrap()),
+            ipl3: data[0x40..0x1000].to_vec(),
+            binary: data[0x1000..].to_vec(),
+            checksum_cache: ChecksumCache::default(),
+        })
+    }
+    
+    /// Quickly checks whether `data` looks like an N64 ROM image, without parsing it. This is a
+    /// heuristic intended for detecting file types, such as in a file picker.
+    /// 
+    /// `data` must contain at least a header and IPL3 (0x1000 bytes), and start with the standard
+    /// `pi_regs` value in any [`ByteOrder`] (see [`ByteOrder::detect()`]), so ROMs which use a
+    /// nonstandard value aren't recognized. If `require_known_cic` is `true`, the IPL3 must also
+    /// use a known checksum algorithm (see [`CicVariant::detect_checksum()`]).
+    pub fn is_valid_n64_image(data: &[u8], require_known_cic: bool) -> bool {
+        let Some(order) = ByteOrder::detect(data).filter(|_| data.len() >= 0x1000) else {
+            return false;
         };
+        if !require_known_cic {
+            return true;
+        }
+        
+        let mut ipl3 = data[0x40..0x1000].to_vec();
+        order.convert(&mut ipl3);
+        CicVariant::detect_checksum(&ipl3) != CicVariant::Unknown
+    }
+    
+    /// Same as [`Self::from_vec()`], but accepts an existing [`Bytes`] buffer.
+    pub fn from_bytes(data: Bytes) -> Result<Self> {
+        Self::from_vec(&data)
+    }
+    
+    /// Consumes the ROM, returning its header, IPL3, and binary without copying them.
+    /// 
+    /// The IPL3 is returned as a `Vec`, since IPL3s which load the ELF themselves (e.g. libdragon's
+    /// debug and release builds) are larger than 0xFC0 bytes.
+    pub fn into_parts(self) -> (Header, Vec<u8>, Vec<u8>) {
+        (self.header, self.ipl3, self.binary)
+    }
+    
+    /// ROM offset of the start of the binary, immediately after the header and IPL3. This is
+    /// 0x1000 for standard IPL3s, but may be later for larger IPL3s (e.g. libdragon's debug and
+    /// release builds).
+    pub fn binary_offset(&self) -> usize {
+        0x40 + self.ipl3.len()
+    }
+    
+    /// Total size of the ROM in bytes, including the header and IPL3. This is the length of
+    /// [`Self::to_vec()`].
+    pub fn total_size(&self) -> usize {
+        self.binary_offset() + self.binary.len()
+    }
+    
+    /// Writes `data` into the binary, starting at the ROM-relative `offset`, and updates the checksum.
+    /// 
+    /// The checksum is only recalculated if the first 1MB of the binary was changed.
+    /// 
+    /// Any existing binary data in that range is overwritten. If the data extends past the current
+    /// end of the binary, the binary is extended (padded with `0xFF` if `offset` is past the end).
+    /// 
+    /// Returns [`Error::InvalidRomOffset`] if `offset` falls within the header or IPL3 (before
+    /// [`Self::binary_offset()`]).
+    pub fn insert_at(&mut self, offset: usize, data: &[u8]) -> Result<()> {
+        if offset < self.binary_offset() {
+            return Err(Error::InvalidRomOffset(offset));
+        }
         
-        if !elf.is_executable() {
-            panic!("ELF is does not contain .boot or is otherwise not executable");
+        let start = offset - self.binary_offset();
+        let end = start + data.len();
+        let covered = start < 0x100000 || self.binary.len() < 0x100000;
+        if self.binary.len() < end {
+            self.binary.resize(end, 0xFF);
         }
+        self.binary[start..end].copy_from_slice(data);
         
-        let mut ptr = elf.sections
-            .iter()
-            .find(|section| section.name == Some(".boot".to_string()))
-            .map(|section| section.addr)
-            .unwrap_or(0);
-        for section in &elf.sections {
-            if section.data.len() == 0 { continue; }
-            
-            let section_name = section.name.as_ref().map(|n| n.as_str()).unwrap_or_default();
-            if !included_sections.contains(&section_name.to_string()) {
-                continue;
-            }
-            
-            let section_addr = section.addr;
-            if ptr < section_addr { // if needed, pad binary until the next section starts
-                binary.resize(binary.len() + (section_addr - ptr) as usize, 0x00);
-                ptr = section_addr;
-            }
-            
-            binary.extend_from_slice(&section.data);
-            
-            ptr += section.data.len() as u64;
+        if covered {
+            self.update_checksum();
         }
         
-        // if binary smaller than 1MB, pad to 1MB
-        if binary.len() < 0x100000 {
-            binary.resize(0x100000, 0xFF);
-        } else if binary.len() > 0x100000 {
-            let total_len = binary.len() + 0x1000;
-            let div = (total_len / 0x100000) + 1;
-            binary.resize((div * 0x100000) - 0x1000, 0xFF);
+        Ok(())
+    }
+    
+    /// Appends `data` to the end of the binary, returning the ROM offset it was written at.
+    /// 
+    /// Afterwards, the ROM is padded with `0xFF` until its total size is a multiple of 256 bytes,
+    /// so any following data begins at an aligned offset.
+    /// 
+    /// The checksum only covers the first 1MB of the binary, so it only needs to be updated (which
+    /// this does automatically) if the binary was smaller than 1MB.
+    pub fn append(&mut self, data: &[u8]) -> usize {
+        let covered = self.binary.len() < 0x100000;
+        let offset = self.binary_offset() + self.binary.len();
+        self.binary.extend_from_slice(data);
+        
+        let misalignment = (self.binary_offset() + self.binary.len()) % 256;
+        if misalignment > 0 {
+            self.binary.resize(self.binary.len() + (256 - misalignment), 0xFF);
         }
         
-        Self {
-            header: Header::generate(&binary, ipl3, name.unwrap_or_else(|| elf.path.file_name().unwrap().to_string()), elf.entry),
-            ipl3: ipl3.to_vec(),
-            binary,
+        if covered {
+            self.update_checksum();
         }
+        
+        offset
     }
     
-    /// Updates the checksum bytes in the ROM's header.
+    /// Appends `data` to the end of the binary, first padding the ROM with `0xFF` so that `data`
+    /// begins at a ROM offset which is a multiple of `align`. Returns the ROM offset `data` was
+    /// written at.
     /// 
-    /// If the ROM's binary is ever modified, this function should be called or else the header will
-    /// likely contain an invalid checksum.
-    pub fn update_checksum(&mut self) {
-        let mut check_ipl3 = self.ipl3.clone();
-        check_ipl3.resize(4032, 0x00);
-        self.header.checksum = Header::calculate_checksum(&self.binary, check_ipl3.try_into().unwrap());
+    /// Unlike [`Self::append()`], no padding is added after `data`. PI DMA requires ROM addresses
+    /// to be at least 2-byte aligned.
+    /// 
+    /// The checksum only covers the first 1MB of the binary, so it only needs to be updated (which
+    /// this does automatically) if the binary was smaller than 1MB.
+    pub fn append_aligned(&mut self, data: &[u8], align: usize) -> usize {
+        let covered = self.binary.len() < 0x100000;
+        let offset = self.extend_aligned(data, align);
+        if covered {
+            self.update_checksum();
+        }
+        
+        offset
     }
     
-    /// Copies ROM components into a Vec.
+    /// Same as [`Self::append_aligned()`], but without updating the checksum.
+    fn extend_aligned(&mut self, data: &[u8], align: usize) -> usize {
+        let misalignment = (self.binary_offset() + self.binary.len()) % align.max(1);
+        if misalignment > 0 {
+            self.binary.resize(self.binary.len() + (align - misalignment), 0xFF);
+        }
+        
+        let offset = self.binary_offset() + self.binary.len();
+        self.binary.extend_from_slice(data);
+        
+        offset
+    }
+    
+    /// Reads a file and appends its contents using [`Self::append()`], returning the ROM offset
+    /// it was written at.
+    pub fn append_file<P: AsRef<Path>>(&mut self, path: P) -> Result<usize> {
+        let data = std::fs::read(path)?;
+        
+        Ok(self.append(&data))
+    }
+    
+    /// Appends a simple filesystem made of named files, returning the ROM offset of its table of
+    /// contents, which is appended first. Each file is then appended aligned to 8 bytes for PI DMA.
     /// 
-    /// Use this to combine `self`'s header, IPL3, and remaining code/assets into a usable N64 ROM.
-    pub fn to_vec(&self) -> Vec<u8> {
-        let mut data = BytesMut::with_capacity(0x40 + self.ipl3.len() + self.binary.len());
+    /// The table of contents is 8-byte aligned, and is made of big-endian values:
+    /// - `u32` number of entries, followed by each entry:
+    ///   - `u32` ROM offset of the file
+    ///   - `u32` length of the file in bytes
+    ///   - `u16` length of the name in bytes, followed by the name (not null-terminated), padded
+    ///     with zeros to a multiple of 4 bytes
+    /// 
+    /// The checksum only covers the first 1MB of the binary, so it only needs to be updated (which
+    /// this does automatically) if the binary was smaller than 1MB.
+    pub fn append_filesystem(&mut self, entries: &[(String, Vec<u8>)]) -> usize {
+        let covered = self.binary.len() < 0x100000;
+        let entry_len = |name: &String| (10 + name.len()).next_multiple_of(4);
+        let toc_len = 4 + entries.iter().map(|(name, _)| entry_len(name)).sum::<usize>();
+        let toc_offset = self.extend_aligned(&vec![0x00; toc_len], 8);
+        
+        let mut toc = BytesMut::with_capacity(toc_len);
+        toc.put_u32(entries.len() as u32);
+        for (name, data) in entries {
+            let offset = self.extend_aligned(data, 8);
+            
+            let end = toc.len() + entry_len(name);
+            toc.put_u32(offset as u32);
+            toc.put_u32(data.len() as u32);
+            toc.put_u16(name.len() as u16);
+            toc.put_slice(name.as_bytes());
+            toc.resize(end, 0x00);
+        }
         
-        data.put_slice(&self.header.to_vec());
-        data.put_slice(&self.ipl3);
-        data.put_slice(&self.binary);
+        let start = toc_offset - self.binary_offset();
+        self.binary[start..start + toc_len].copy_from_slice(&toc);
         
-        data.to_vec()
+        if covered {
+            self.update_checksum();
+        }
+        
+        toc_offset
     }
-}
\ No newline at end of file
+    
+    /// Pads the end of the binary with `0xFF`, so that the entire ROM (including the header and
+    /// IPL3) is exactly `total_size` bytes.
+    /// 
+    /// The checksum only covers the first 1MB of the binary, so it only needs to be updated (which
+    /// this does automatically) if the binary was smaller than 1MB.
+    /// 
+    /// Returns [`Error::RomTooLarge`] if the ROM is already larger than `total_size`.
+    pub fn pad_to(&mut self, total_size: usize) -> Result<()> {
+        let current_size = self.total_size();
+        if current_size > total_size {
+            return Err(Error::RomTooLarge(current_size, total_size));
+        }
+        
+        let checksum_changed = self.binary.len() < 0x100000;
+        self.binary.resize(total_size - self.binary_offset(), 0xFF);
+        if checksum_changed {
+            self.update_checksum();
+        }
+        
+        Ok(())
+    }
+    
+    /// Pads the end of the binary with `0xFF` until its length is a multiple of `align`, returning
+    /// the number of bytes added. The binary's length should be a multiple of 2 for PI DMA, but
+    /// nothing else enforces it (see [`Self::binary`]), so this should be called after any
+    /// change which may leave it unaligned.
+    /// 
+    /// The checksum is updated automatically if the padding is within the first 1MB of the binary.
+    pub fn align_binary(&mut self, align: usize) -> usize {
+        let padding = self.binary.len().next_multiple_of(align.max(1)) - self.binary.len();
+        if padding > 0 {
+            self.edit_binary(|binary| binary.resize(binary.len() + padding, 0xFF));
+        }
+        
+        padding
+    }
+    
+    /// Removes trailing `0xFF` padding from the end of the binary, returning the number of bytes
+    /// removed. The binary is never trimmed below 1MB, so the checksum stays valid.
+    /// 
+    /// This is intended for archiving or diffing ROMs. A trimmed ROM may not boot on flashcarts or
+    /// hardware which expect the ROM size to be aligned, so it shouldn't be used for flashing.
+    pub fn trim_padding(&mut self) -> usize {
+        let end = self.binary.iter().rposition(|&byte| byte != 0xFF).map_or(0, |last| last + 1);
+        let trimmed_len = end.max(0x100000).min(self.binary.len());
+        let removed = self.binary.len() - trimmed_len;
+        self.binary.truncate(trimmed_len);
+        
+        removed
+    }
+    
+    /// Sets the image name in the ROM's header. See [`Header::set_name()`].
+    /// 
+    /// The header isn't covered by the checksum, so it doesn't need to be updated.
+    pub fn set_name<S: AsRef<str>>(&mut self, name: S) {
+        self.header.set_name(name);
+    }
+    
+    /// Runs `edit` on the binary, then updates the checksum using [`Self::update_checksum()`].
+    pub fn edit_binary<F: FnOnce(&mut Vec<u8>)>(&mut self, edit: F) {
+        edit(&mut self.binary);
+        self.update_checksum();
+    }
+    
+    /// Replaces the IPL3 (e.g. to switch between CIC variants without rebuilding from the ELF), then
+    /// updates the checksum, since the checksum algorithm depends on the IPL3.
+    /// 
+    /// The binary isn't changed, so this is only meant for standard 0xFC0 byte IPL3s. ROMs using an
+    /// IPL3 which loads the ELF file itself (e.g. libdragon's debug and release builds) have a
+    /// binary laid out for that IPL3, and won't boot after replacing it.
+    pub fn replace_ipl3(&mut self, ipl3: [u8; 0xFC0]) {
+        self.ipl3 = ipl3.to_vec();
+        self.update_checksum();
+    }
+    
+    /// Updates the checksum bytes in the ROM's header.
+    /// 
+    /// If the ROM's binary is ever modified directly, this function should be called or else the
+    /// header will likely contain an invalid checksum. [`Self::edit_binary()`] does this
+    /// automatically. 64DD disk images always use a checksum of `0u64`.
+    /// 
+    /// The ROM keeps a copy of the IPL3 and the first 1MB of the binary from the last call, and
+    /// only recalculates the checksum if either has changed since then. Comparing them is much
+    /// cheaper than recalculating it, so calling this after each append in a loop is cheap.
+    pub fn update_checksum(&mut self) {
+        let check_ipl3 = self.check_ipl3();
+        self.header.checksum = match self.header.kind() {
+            RomKind::Cartridge => self.checksum_cache.checksum(&self.binary, check_ipl3),
+            RomKind::Disk64DD => 0,
+        };
+    }
+    
+    /// Checks if the checksum in the ROM's header matches a freshly computed checksum.
+    /// 
+    /// Returns `None` if the IPL3 isn't a known CIC variant (see [`CicVariant::detect_checksum()`]),
+    /// or if the ROM is a 64DD disk image, since the checksum can't be computed.
+    pub fn verify_checksum(&self) -> Option<bool> {
+        let check_ipl3 = self.check_ipl3();
+        if CicVariant::detect_checksum(&check_ipl3) == CicVariant::Unknown || self.header.kind() == RomKind::Disk64DD {
+            return None;
+        }
+        
+        Some(self.header.checksum == Header::calculate_checksum(&self.binary, check_ipl3))
+    }
+    
+    /// Compares this ROM to another, reporting which header fields differ, whether the IPL3s
+    /// differ, and which ranges of the binary differ. See [`RomDiff`].
+    pub fn diff(&self, other: &Rom) -> RomDiff {
+        let (a, b) = (&self.header, &other.header);
+        let header_fields = [
+            ("pi_regs", a.pi_regs != b.pi_regs),
+            ("clockrate", a.clockrate != b.clockrate),
+            ("pc", a.pc != b.pc),
+            ("unknown0", a.unknown0 != b.unknown0),
+            ("release", a.release != b.release),
+            ("checksum", a.checksum != b.checksum),
+            ("unknown1", a.unknown1 != b.unknown1),
+            ("image_name", a.image_name != b.image_name),
+            ("unknown2", a.unknown2 != b.unknown2),
+            ("media_format", a.media_format != b.media_format),
+            ("cart_id", a.cart_id != b.cart_id),
+            ("country", a.country != b.country),
+            ("revision", a.revision != b.revision),
+        ].into_iter().filter(|(_, differs)| *differs).map(|(name, _)| name.to_string()).collect();
+        
+        let mut binary_ranges: Vec<Range<usize>> = vec![];
+        for (offset, (a, b)) in self.binary.iter().zip(&other.binary).enumerate() {
+            if a == b {
+                continue;
+            }
+            match binary_ranges.last_mut() {
+                Some(range) if range.end == offset => range.end += 1,
+                _ => binary_ranges.push(offset..offset + 1),
+            }
+        }
+        // bytes past the end of the shorter binary always differ
+        let (shorter, longer) = (self.binary.len().min(other.binary.len()), self.binary.len().max(other.binary.len()));
+        if shorter < longer {
+            match binary_ranges.last_mut() {
+                Some(range) if range.end == shorter => range.end = longer,
+                _ => binary_ranges.push(shorter..longer),
+            }
+        }
+        
+        RomDiff {
+            header_fields,
+            ipl3: self.ipl3 != other.ipl3,
+            binary_ranges,
+        }
+    }
+    
+    /// Summarizes the ROM's header and IPL3 in a human-readable form.
+    pub fn info(&self) -> RomInfo {
+        let header = &self.header;
+        
+        RomInfo {
+            name: String::from_utf8_lossy(&header.image_name).trim_end_matches([' ', '\0']).to_string(),
+            cic: CicVariant::detect(&self.check_ipl3()),
+            media_format: match header.media_format {
+                b'N' => "Cartridge".to_string(),
+                b'D' => "64DD disk".to_string(),
+                b'C' => "Cartridge (expandable)".to_string(),
+                b'E' => "64DD expansion".to_string(),
+                b'Z' => "Aleck64".to_string(),
+                format => format!("Unknown ({format:#04X})"),
+            },
+            cart_id: match header.cart_id.to_be_bytes() {
+                id if id.iter().all(|byte| byte.is_ascii_graphic()) => String::from_utf8_lossy(&id).to_string(),
+                _ => format!("{:#06X}", header.cart_id),
+            },
+            country: header.country_enum().to_string(),
+            revision: header.revision,
+        }
+    }
+    
+    /// The IPL3 trimmed or padded with zeros to exactly 4032 bytes, as used by the checksum.
+    fn check_ipl3(&self) -> [u8; 0x1000 - 0x40] {
+        let mut check_ipl3 = self.ipl3.clone();
+        check_ipl3.resize(4032, 0x00);
+        
+        check_ipl3.try_into().unwrap()
+    }
+    
+    /// Copies ROM components into a Vec.
+    /// 
+    /// Use this to combine `self`'s header, IPL3, and remaining code/assets into a usable N64 ROM.
+    pub fn to_vec(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(0x40 + self.ipl3.len() + self.binary.len());
+        self.write_to(&mut data).expect("writing to a Vec should never fail");
+        
+        data
+    }
+    
+    /// [`CRC`] of the entire ROM as produced by [`Self::to_vec()`], for detecting if a ROM's
+    /// contents have changed.
+    /// 
+    /// Unlike the header checksum, this covers every byte of the ROM, including the header itself.
+    pub fn content_hash(&self) -> u32 {
+        let mut digest = CRC.digest();
+        digest.update(&self.header.to_bytes());
+        digest.update(&self.ipl3);
+        digest.update(&self.binary);
+        
+        digest.finalize()
+    }
+    
+    /// Writes the ROM's header, IPL3, and remaining code/assets sequentially to `writer`.
+    /// 
+    /// Unlike [`Self::to_vec()`], this doesn't require assembling the entire ROM in memory first.
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&self.header.to_bytes())?;
+        writer.write_all(&self.ipl3)?;
+        writer.write_all(&self.binary)
+    }
+    
+    /// Same as [`Self::write_to()`], but the ROM is converted into the provided byte order as it's
+    /// written.
+    pub fn write_to_with_order<W: Write>(&self, writer: &mut W, order: ByteOrder) -> io::Result<()> {
+        if order == ByteOrder::Z64 {
+            return self.write_to(writer);
+        }
+        
+        // The buffer size must be a multiple of 4, so conversions never straddle two chunks.
+        const CHUNK_SIZE: usize = 0x10000;
+        let mut chunk = Vec::with_capacity(CHUNK_SIZE);
+        for mut part in [&self.header.to_bytes()[..], &self.ipl3, &self.binary] {
+            while !part.is_empty() {
+                let len = (CHUNK_SIZE - chunk.len()).min(part.len());
+                chunk.extend_from_slice(&part[..len]);
+                part = &part[len..];
+                
+                if chunk.len() == CHUNK_SIZE {
+                    order.convert(&mut chunk);
+                    writer.write_all(&chunk)?;
+                    chunk.clear();
+                }
+            }
+        }
+        order.convert(&mut chunk);
+        writer.write_all(&chunk)
+    }
+    
+    /// Same as [`Self::to_vec()`], but the assembled ROM is converted into the provided byte order.
+    pub fn to_vec_with_order(&self, order: ByteOrder) -> Vec<u8> {
+        let mut data = self.to_vec();
+        order.convert(&mut data);
+        
+        data
+    }
+}
+impl From<&Elf> for Rom {
+    /// Generates a ROM using the bundled release build of libdragon's IPL3, with the default name.
+    /// 
+    /// This is the same as the runner's default behavior. The release IPL3 loads the entire ELF
+    /// file itself, so no sections need to be selected. See [`Rom::with_libdragon()`] for the
+    /// other builds.
+    fn from(elf: &Elf) -> Self {
+        Self::with_libdragon(elf, LibdragonIpl3::Release, None, vec![]).expect("bundled libdragon IPL3 should always be valid")
+    }
+}
+
+/// Differences between two ROMs, created using [`Rom::diff()`].
+#[derive(Clone, Debug, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RomDiff {
+    /// Names of the [`Header`] fields which differ (e.g. `checksum`), in header order.
+    pub header_fields: Vec<String>,
+    /// Whether the IPL3s differ, including in length.
+    pub ipl3: bool,
+    /// Ranges of offsets into the binary (not the whole ROM) which differ, in order. If one binary
+    /// is longer than the other, the extra bytes are part of the last range.
+    pub binary_ranges: Vec<Range<usize>>,
+}
+impl RomDiff {
+    /// Returns `true` if the ROMs are identical.
+    pub fn is_empty(&self) -> bool {
+        self.header_fields.is_empty() && !self.ipl3 && self.binary_ranges.is_empty()
+    }
+}
+impl Display for RomDiff {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.is_empty() {
+            return write!(f, "ROMs are identical");
+        }
+        
+        match self.header_fields.is_empty() {
+            true => writeln!(f, "Header: identical")?,
+            false => writeln!(f, "Header: differs in {}", self.header_fields.join(", "))?,
+        }
+        writeln!(f, "IPL3:   {}", if self.ipl3 { "differs" } else { "identical" })?;
+        match self.binary_ranges.len() {
+            0 => write!(f, "Binary: identical"),
+            count => {
+                let len: usize = self.binary_ranges.iter().map(|range| range.len()).sum();
+                write!(f, "Binary: {len:#X} bytes differ in {count} range(s)")?;
+                for range in &self.binary_ranges {
+                    write!(f, "\n  {:#X}..{:#X} ({:#X} bytes)", range.start, range.end, range.len())?;
+                }
+                Ok(())
+            },
+        }
+    }
+}
+
+/// Human-readable summary of a [`Rom`], created using [`Rom::info()`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RomInfo {
+    /// Image name, without any trailing padding.
+    pub name: String,
+    /// CIC variant detected from the IPL3.
+    pub cic: CicVariant,
+    pub media_format: String,
+    /// Two character cartridge ID, or its hex value if the ID isn't printable ASCII.
+    pub cart_id: String,
+    pub country: String,
+    pub revision: u8,
+}
+impl Display for RomInfo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Name:         {}", self.name)?;
+        writeln!(f, "CIC:          {}", self.cic)?;
+        writeln!(f, "Media format: {}", self.media_format)?;
+        writeln!(f, "Cart ID:      {}", self.cart_id)?;
+        writeln!(f, "Country:      {}", self.country)?;
+        write!(f, "Revision:     {}", self.revision)
+    }
+}
+
+/// Describes where an ELF section was placed in a generated [`Rom`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SectionPlacement {
+    pub name: String,
+    /// Offset of the section from the start of the ROM (including the header and IPL3).
+    pub rom_offset: usize,
+    /// Virtual address of the section.
+    pub vaddr: u64,
+    pub len: usize,
+    /// Number of padding bytes inserted between the previous section and this one.
+    pub padding_before: usize,
+}
+
+/// Builder for generating a [`Rom`] from an [`Elf`], with more control than [`Rom::new()`].
+/// 
+/// Unless changed, the builder uses the same defaults as [`Rom::new()`].
+#[derive(Clone, Debug)]
+pub struct RomBuilder<'a> {
+    elf: &'a Elf,
+    ipl3: &'a [u8],
+    name: Option<String>,
+    sections: Vec<String>,
+    section_kinds: Vec<SectionKind>,
+    auto_sections: bool,
+    base_section: Option<String>,
+    placements: Vec<(String, usize)>,
+    injected: Vec<ElfSection>,
+    strict_entry: bool,
+    preserve_order: bool,
+    min_pad: bool,
+    fill: u8,
+    kind: RomKind,
+}
+impl<'a> RomBuilder<'a> {
+    /// Creates a new builder using the provided ELF and IPL3 (excluding the ROM header).
+    pub fn new(elf: &'a Elf, ipl3: &'a [u8]) -> Self {
+        Self {
+            elf,
+            ipl3,
+            name: None,
+            sections: vec![],
+            section_kinds: vec![],
+            auto_sections: false,
+            base_section: None,
+            placements: vec![],
+            injected: vec![],
+            strict_entry: false,
+            preserve_order: false,
+            min_pad: true,
+            fill: 0xFF,
+            kind: RomKind::Cartridge,
+        }
+    }
+    
+    /// Name to use in the ROM's header. If not set, [`Header::default_name()`] of the Elf artifact
+    /// will be used.
+    pub fn name<S: Into<String>>(mut self, name: S) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+    
+    /// Names of the ELF sections to include in the ROM, _instead of_ the default set. If empty,
+    /// the default set is used.
+    /// 
+    /// A name ending in `*` matches any section starting with the rest of the name (e.g. `.text*`
+    /// matches `.text`, `.text.boot`, and `.text.hot`). Otherwise, names must match exactly.
+    pub fn sections(mut self, sections: Vec<String>) -> Self {
+        self.sections = sections;
+        self
+    }
+    
+    /// Kinds of ELF sections to include in the ROM, regardless of their names (e.g.
+    /// [`SectionKind::Text`] and [`SectionKind::ReadOnlyData`]).
+    /// 
+    /// If not empty, this is used _instead of_ the section names from [`Self::sections()`], so
+    /// only one selection mechanism applies per ROM. The .boot section is still required, and
+    /// must be one of the included kinds.
+    pub fn section_kinds(mut self, section_kinds: Vec<SectionKind>) -> Self {
+        self.section_kinds = section_kinds;
+        self
+    }
+    
+    /// If `true`, every allocated ELF section (`SHF_ALLOC`) is included in the ROM, regardless of
+    /// its name or kind. See [`Elf::allocated_sections()`].
+    /// 
+    /// This is used _instead of_ [`Self::sections()`] and [`Self::section_kinds()`], and is more
+    /// robust for nonstandard linker scripts, since non-allocated sections (e.g. `.comment` and
+    /// `.debug_*`) never belong in a ROM.
+    pub fn auto_sections(mut self, auto_sections: bool) -> Self {
+        self.auto_sections = auto_sections;
+        self
+    }
+    
+    /// Name of the ELF section whose address the ROM's layout starts at. If not set, the layout
+    /// starts at the lowest executable section that's included (normally .boot).
+    /// 
+    /// Any gap between this address and the first included section is padded with `0x00`. For
+    /// example, a base section at `0x80000000` with the first included section at `0x80000400`
+    /// places that section 0x400 bytes into the binary. Included sections below this address are
+    /// reported as [`Error::SectionBeforeBase`].
+    pub fn base_section<S: Into<String>>(mut self, name: S) -> Self {
+        self.base_section = Some(name.into());
+        self
+    }
+    
+    /// Places the included section named `name` at the ROM offset `rom_offset` (including the
+    /// header and IPL3), instead of at an offset based on its address. Can be used multiple times
+    /// to place multiple sections.
+    /// 
+    /// This is useful for code which is copied to a different address at runtime, such as a
+    /// second-stage loader. The remaining sections are laid out normally, and placed sections are
+    /// written after them at their chosen offsets, with any gaps padded with `0x00`.
+    /// 
+    /// When building, [`Error::MissingElfSection`] is returned if `name` isn't an included section,
+    /// [`Error::InvalidRomOffset`] if `rom_offset` falls within the header or IPL3, and
+    /// [`Error::OverlappingSections`] if a placed section overlaps another placed section or the
+    /// normally laid out sections.
+    pub fn place<S: Into<String>>(mut self, name: S, rom_offset: usize) -> Self {
+        self.placements.push((name.into(), rom_offset));
+        self
+    }
+    
+    /// Adds a section which isn't in the ELF (e.g. assets read from a separate file, see
+    /// [`ElfSection::from_data()`]), so it doesn't need to be linked into the ELF. Can be used
+    /// multiple times to add multiple sections.
+    /// 
+    /// Injected sections are always included, and are placed by address like any other included
+    /// section (or with [`Self::place()`]), so they may not overlap any of them.
+    pub fn inject_section(mut self, section: ElfSection) -> Self {
+        self.injected.push(section);
+        self
+    }
+    
+    /// If `true`, building returns [`Error::EntryNotInSection`] when the ELF's entrypoint isn't
+    /// within the data of any included section. Otherwise (the default), a warning is logged.
+    /// 
+    /// An entrypoint outside every included section usually means the linker script is wrong, and
+    /// the ROM will hang on a black screen after jumping to uninitialized memory.
+    pub fn strict_entry(mut self, strict_entry: bool) -> Self {
+        self.strict_entry = strict_entry;
+        self
+    }
+    
+    /// Byte used to pad the end of the ROM to its final size (`0xFF` by default).
+    /// 
+    /// This only affects the padding _after_ all sections. Gaps between sections are always padded
+    /// with `0x00`, since they represent uninitialized memory. For binaries smaller than 1MB, the
+    /// fill byte is covered by the checksum.
+    pub fn fill(mut self, fill: u8) -> Self {
+        self.fill = fill;
+        self
+    }
+    
+    /// If `true`, included sections are placed in the order they're declared in the ELF's section
+    /// header table (see [`ElfSection::index`]), instead of in address order. Injected sections
+    /// are placed after them.
+    /// 
+    /// This is for linker scripts which intentionally place a section after others in the ROM,
+    /// despite it loading at a lower address (e.g. init code which is copied to RAM). Each section
+    /// is still padded up to its address when that address is after the highest end address of
+    /// the sections placed so far, but a section whose address is lower than that is placed
+    /// directly after the previous section with no padding. As a result, the offset between such a
+    /// section's ROM offset and its address differs from the other sections, and overlapping
+    /// addresses aren't reported as [`Error::OverlappingSections`].
+    /// 
+    /// Since padding is relative to the highest end address, sections after a lower addressed one
+    /// aren't padded to make up for the space it occupies. For example, with `.boot` at
+    /// `0x80000400`, `.init` at `0x80000100`, and `.rodata` at `0x80000500` (each `0x100` bytes,
+    /// in that order), `.init` is placed at ROM offset `0x1100` and `.rodata` at `0x1200`.
+    pub fn preserve_order(mut self, preserve_order: bool) -> Self {
+        self.preserve_order = preserve_order;
+        self
+    }
+    
+    /// If `false`, binaries smaller than 1MB aren't padded to 1MB, so the ROM can be much smaller.
+    /// The binary is still padded to a multiple of 8 bytes, and binaries larger than 1MB are still
+    /// padded to a multiple of 1MB (`true` by default).
+    /// 
+    /// The checksum always covers 1MB of the binary, so on hardware a CIC IPL3 reads past the end of
+    /// a smaller ROM and its checksum check fails. This is only useful with custom IPL3s which don't
+    /// verify the checksum, and a warning is logged if the IPL3 is a known CIC variant.
+    pub fn min_pad(mut self, min_pad: bool) -> Self {
+        self.min_pad = min_pad;
+        self
+    }
+    
+    /// Kind of media the ROM is intended for. See [`RomKind`] for the limitations of 64DD support.
+    pub fn kind(mut self, kind: RomKind) -> Self {
+        self.kind = kind;
+        self
+    }
+    
+    /// Generates the ROM. See [`Rom::try_new()`] for possible errors.
+    pub fn build(self) -> Result<Rom> {
+        self.build_with_layout().map(|(rom, _)| rom)
+    }
+    
+    /// Same as [`Self::build()`], but also returns where each included section was placed in the
+    /// ROM, in order of placement.
+    /// 
+    /// Sections are placed relative to their address, so a section whose address doesn't satisfy
+    /// its declared alignment ([`ElfSection::align`]) can't be padded into alignment without
+    /// moving it away from that address. A warning is logged instead, since this usually means
+    /// the linker script is wrong.
+    pub fn build_with_layout(self) -> Result<(Rom, Vec<SectionPlacement>)> {
+        let elf = self.elf;
+        let ipl3 = self.ipl3;
+        let mut binary = vec![];
+        let (placed, included): (Vec<_>, Vec<_>) = self.included_sections()
+            .into_iter()
+            .partition(|section| self.placements.iter().any(|(name, _)| section.name.as_deref() == Some(name)));
+        
+        // unless a base section is set, the layout starts at the lowest executable section, which
+        // is normally .boot
+        let executable = match included.iter().find(|section| section.is_executable()) {
+            Some(executable) => executable,
+            None if elf.section_by_name(".boot").is_none() => return Err(self.missing_sections()),
+            None => return Err(Error::NotExecutable),
+        };
+        if !included.iter().chain(&placed).any(|section| section.contains(elf.entry)) {
+            if self.strict_entry {
+                return Err(Error::EntryNotInSection(elf.entry));
+            }
+            log::warn!("ELF entrypoint ({:#X}) isn't within any included section, so the ROM will likely hang on boot", elf.entry);
+        }
+        let base = match &self.base_section {
+            Some(name) => elf.section_by_name(name).ok_or_else(|| Error::MissingElfSection(name.clone()))?,
+            None => executable,
+        };
+        // highest end address of the sections placed so far
+        let mut ptr = base.addr;
+        let mut previous_name = base.name.as_deref().unwrap_or_default();
+        let mut layout = vec![];
+        for section in included {
+            let uninitialized = section.kind == SectionKind::UninitializedData;
+            let section_len = section.rom_len();
+            let section_name = section.name.as_deref().unwrap_or_default();
+            
+            let section_addr = section.addr;
+            if !section.is_aligned() {
+                log::warn!("{section_name} is at {section_addr:#X}, which isn't aligned to its declared alignment of {:#X}", section.align);
+            }
+            let mut padding_before = 0;
+            if self.preserve_order && section_addr < ptr {
+                // the declared order goes backwards in memory, so the section can't be padded to
+                log::debug!("{section_name} is at {section_addr:#X}, which is before the end of {previous_name}, so it's placed directly after it");
+            } else if section_addr < base.addr {
+                return Err(Error::SectionBeforeBase(section_name.to_string(), base.name.clone().unwrap_or_default()));
+            } else if section_addr < ptr {
+                return Err(Error::OverlappingSections(previous_name.to_string(), section_name.to_string()));
+            } else if ptr < section_addr { // if needed, pad binary until the next section starts
+                padding_before = (section_addr - ptr) as usize;
+                binary.resize(binary.len() + padding_before, 0x00);
+            }
+            
+            layout.push(SectionPlacement {
+                name: section_name.to_string(),
+                rom_offset: 0x40 + ipl3.len() + binary.len(),
+                vaddr: section_addr,
+                len: section_len as usize,
+                padding_before,
+            });
+            
+            log::debug!("Placed {section_name} ({section_len:#X} bytes) at ROM offset {:#X}", 0x40 + ipl3.len() + binary.len());
+            if uninitialized {
+                binary.resize(binary.len() + section_len as usize, 0x00);
+            } else {
+                binary.extend_from_slice(section.data(elf));
+            }
+            
+            ptr = ptr.max(section_addr + section_len);
+            previous_name = section_name;
+        }
+        
+        // sections with a chosen ROM offset are placed after everything else, in order of offset
+        let mut placements = self.placements.clone();
+        placements.sort_by_key(|(_, rom_offset)| *rom_offset);
+        for (name, rom_offset) in placements {
+            let section = placed.iter().find(|section| section.name.as_deref() == Some(&name)).ok_or_else(|| Error::MissingElfSection(name.clone()))?;
+            let section_len = section.rom_len();
+            
+            let start = rom_offset.checked_sub(0x40 + ipl3.len()).ok_or(Error::InvalidRomOffset(rom_offset))?;
+            if start < binary.len() {
+                return Err(Error::OverlappingSections(previous_name.to_string(), name));
+            }
+            let padding_before = start - binary.len();
+            binary.resize(start, 0x00);
+            
+            layout.push(SectionPlacement {
+                name: name.clone(),
+                rom_offset,
+                vaddr: section.addr,
+                len: section_len as usize,
+                padding_before,
+            });
+            
+            log::debug!("Placed {name} ({section_len:#X} bytes) at chosen ROM offset {rom_offset:#X}");
+            if section.kind == SectionKind::UninitializedData {
+                binary.resize(binary.len() + section_len as usize, 0x00);
+            } else {
+                binary.extend_from_slice(section.data(elf));
+            }
+            
+            previous_name = section.name.as_deref().unwrap_or_default();
+        }
+        
+        // if binary smaller than 1MB, pad to 1MB
+        if binary.len() < 0x100000 && !self.min_pad {
+            if CicVariant::detect_checksum(ipl3) != CicVariant::Unknown {
+                log::warn!("The binary is smaller than 1MB and isn't padded, so the IPL3 will fail to verify its checksum on hardware");
+            }
+            binary.resize(binary.len().next_multiple_of(8), self.fill);
+        } else if binary.len() < 0x100000 {
+            binary.resize(0x100000, self.fill);
+        } else if binary.len() > 0x100000 {
+            // otherwise, pad the entire ROM to the next multiple of 1MB
+            let total_len = binary.len() + 0x1000;
+            let div = total_len.div_ceil(0x100000);
+            binary.resize((div * 0x100000) - 0x1000, self.fill);
+        }
+        
+        // the checksum always covers exactly 1MB of the binary, which must include the padding
+        debug_assert!(binary.len() >= 0x100000 || !self.min_pad, "binary must be padded to at least 1MB before calculating the checksum");
+        
+        let header = HeaderBuilder::new()
+            .name(self.name.unwrap_or_else(|| Header::default_name(&elf.path)))
+            .entry(elf.entry_u32());
+        let header = match self.kind {
+            RomKind::Cartridge => {
+                log::debug!("Detected CIC: {}", CicVariant::detect(ipl3));
+                header.build(&binary, ipl3)
+            },
+            RomKind::Disk64DD => header.media_format(RomKind::MEDIA_FORMAT_64DD).header,
+        };
+        
+        Ok((Rom {
+            header,
+            ipl3: ipl3.to_vec(),
+            binary,
+            checksum_cache: ChecksumCache::default(),
+        }, layout))
+    }
+    
+    /// Error for when .boot is missing, listing every other requested section that's missing too.
+    fn missing_sections(&self) -> Error {
+        let mut missing = match (self.section_kinds.is_empty() && !self.auto_sections, self.sections.is_empty()) {
+            (false, _) => vec![],
+            (true, true) => self.elf.unmatched_sections(DEFAULT_SECTIONS),
+            (true, false) => self.elf.unmatched_sections(&self.sections),
+        };
+        if !missing.iter().any(|name| name == ".boot") {
+            missing.insert(0, ".boot".into());
+        }
+        
+        match missing.len() {
+            1 => Error::MissingElfSection(missing.remove(0)),
+            _ => Error::MissingElfSections(missing),
+        }
+    }
+    
+    /// ELF sections selected by this builder, along with any injected sections, in address order
+    /// (or declared order, see [`Self::preserve_order()`]). See [`Elf::included_sections()`].
+    fn included_sections(&self) -> Vec<&ElfSection> {
+        let mut sections = match (self.auto_sections, self.section_kinds.is_empty()) {
+            (true, _) => self.elf.allocated_sections(),
+            (false, true) => self.elf.included_sections(&self.sections),
+            (false, false) => self.elf.sections
+                .iter()
+                .filter(|section| section.rom_len() > 0 && self.section_kinds.contains(&section.kind))
+                .collect(),
+        };
+        if !self.injected.is_empty() {
+            sections.extend(&self.injected);
+            sections.sort_by_key(|section| section.addr);
+        }
+        if self.preserve_order {
+            sections.sort_by_key(|section| section.index);
+        }
+        
+        sections
+    }
+}
+
+/// Checks if a section name matches an inclusion spec. Specs ending in `*` match by prefix, and all
+/// other specs must match exactly.
+pub(crate) fn section_matches(spec: &str, name: &str) -> bool {
+    match spec.strip_suffix('*') {
+        Some(prefix) => name.starts_with(prefix),
+        None => spec == name,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ipl3::LIBDRAGON_IPL3_COMPAT;
+    use crate::testutil::{minimal_elf, ElfBuilder};
+    use super::*;
+    
+    #[test]
+    fn country_code_is_case_insensitive() {
+        assert_eq!("e".parse::<Country>().unwrap(), Country::Usa);
+        assert_eq!("E".parse::<Country>().unwrap(), Country::Usa);
+        assert_eq!("j".parse::<Country>().unwrap(), Country::Japan);
+        assert_eq!("7".parse::<Country>().unwrap(), Country::Beta);
+        assert_eq!("eu".parse::<Country>().unwrap(), Country::Europe);
+        assert_eq!("z".parse::<Country>().unwrap(), Country::Unknown(b'Z'));
+    }
+    
+    #[test]
+    fn odd_length_binary_is_allowed() {
+        let mut data = vec![0x00; 0x1000 + 0x101];
+        data[..4].copy_from_slice(&[0x80, 0x37, 0x12, 0x40]);
+        
+        let mut rom = Rom::from_vec(&data).unwrap();
+        assert_eq!(rom.to_vec(), data);
+        assert_eq!(rom.content_hash(), CRC.checksum(&data));
+        rom.update_checksum();
+        
+        assert_eq!(rom.align_binary(2), 1);
+        assert_eq!(rom.binary.len(), 0x102);
+        assert_eq!(rom.binary.last(), Some(&0xFF));
+        assert_eq!(rom.align_binary(2), 0);
+    }
+    
+    #[test]
+    fn preserve_order_pads_relative_to_highest_end() {
+        let elf = ElfBuilder::new(0x80000400)
+            .text(".boot", 0x80000400, vec![0x11; 0x100])
+            .text(".init", 0x80000100, vec![0x22; 0x100])
+            .rodata(".rodata", 0x80000500, vec![0x33; 0x100])
+            .build_elf()
+            .unwrap();
+        let (rom, layout) = RomBuilder::new(&elf, &[0x00; 0xFC0])
+            .sections(vec![".boot".into(), ".init".into(), ".rodata".into()])
+            .preserve_order(true)
+            .build_with_layout()
+            .unwrap();
+        
+        let offsets: Vec<_> = layout.iter().map(|placement| (placement.name.as_str(), placement.rom_offset, placement.padding_before)).collect();
+        assert_eq!(offsets, [(".boot", 0x1000, 0), (".init", 0x1100, 0), (".rodata", 0x1200, 0)]);
+        assert_eq!(&rom.binary[0x200..0x300], &[0x33; 0x100]);
+    }
+    
+    #[test]
+    fn disk_64dd_checksum_is_zero() {
+        let elf = minimal_elf().build_elf().unwrap();
+        let ipl3 = &LIBDRAGON_IPL3_COMPAT[0x40..];
+        assert_ne!(RomBuilder::new(&elf, ipl3).build().unwrap().header.checksum, 0);
+        
+        let mut rom = RomBuilder::new(&elf, ipl3).kind(RomKind::Disk64DD).build().unwrap();
+        assert_eq!(rom.header.media_format, RomKind::MEDIA_FORMAT_64DD);
+        assert_eq!(rom.header.checksum, 0);
+        assert_eq!(rom.verify_checksum(), None);
+        
+        rom.append(&[0x12; 0x10]);
+        rom.update_checksum();
+        assert_eq!(rom.header.checksum, 0);
+    }
+    
+    #[test]
+    fn libdragon_loader_ipl3s_have_no_checksum() {
+        let elf = minimal_elf().build_elf().unwrap();
+        for version in [LibdragonIpl3::Debug, LibdragonIpl3::Release] {
+            let rom = Rom::with_libdragon(&elf, version, None, vec![]).unwrap();
+            assert_eq!(rom.header.checksum, 0, "{version:?}");
+            assert_eq!(rom.verify_checksum(), None, "{version:?}");
+        }
+        
+        let rom = Rom::with_libdragon(&elf, LibdragonIpl3::Compat, None, vec![]).unwrap();
+        assert_ne!(rom.header.checksum, 0);
+        assert_eq!(rom.verify_checksum(), Some(true));
+    }
+    
+    #[test]
+    fn from_vec_round_trips() {
+        let elf = minimal_elf().build_elf().unwrap();
+        let data = RomBuilder::new(&elf, &LIBDRAGON_IPL3_COMPAT[0x40..]).build().unwrap().to_vec();
+        
+        let rom = Rom::from_vec(&data).unwrap();
+        assert_eq!(rom.to_vec(), data);
+        assert_eq!(Rom::from_bytes(Bytes::from(data.clone())).unwrap(), rom);
+    }
+    
+    #[test]
+    fn from_vec_rejects_small_roms() {
+        assert!(matches!(Rom::from_vec(&[0x00; 0xFFF]), Err(Error::RomTooSmall(0xFFF))));
+        assert!(matches!(Rom::from_vec(&[]), Err(Error::RomTooSmall(0))));
+        assert!(Rom::from_vec(&[0x00; 0x1000]).unwrap().binary.is_empty());
+    }
+    
+    #[test]
+    fn elf_rom_round_trips_through_from_vec() {
+        let elf = minimal_elf().build_elf().unwrap();
+        for ipl3 in [LibdragonIpl3::Compat, LibdragonIpl3::Release] {
+            let rom = Rom::with_libdragon(&elf, ipl3, Some("Round Trip".into()), vec![]).unwrap();
+            let parsed = Rom::from_vec(&rom.to_vec()).unwrap();
+            
+            assert_eq!(parsed.header, rom.header, "{ipl3:?}");
+            assert_eq!(parsed.to_vec(), rom.to_vec(), "{ipl3:?}");
+            // from_vec assumes a 0xFC0 byte IPL3, so larger IPL3s end up partly in the binary
+            if ipl3 == LibdragonIpl3::Compat {
+                assert_eq!(parsed, rom);
+            }
+        }
+    }
+    
+    #[test]
+    fn byte_order_round_trips() {
+        let elf = minimal_elf().build_elf().unwrap();
+        let rom = RomBuilder::new(&elf, &LIBDRAGON_IPL3_COMPAT[0x40..]).build().unwrap();
+        let z64 = rom.to_vec();
+        
+        let v64 = rom.to_vec_with_order(ByteOrder::V64);
+        assert_eq!(&v64[..4], &[0x37, 0x80, 0x40, 0x12]);
+        assert_eq!(ByteOrder::detect(&v64), Some(ByteOrder::V64));
+        let mut converted = v64.clone();
+        ByteOrder::V64.convert(&mut converted);
+        assert_eq!(converted, z64);
+        
+        let n64 = rom.to_vec_with_order(ByteOrder::N64);
+        assert_eq!(&n64[..4], &[0x40, 0x12, 0x37, 0x80]);
+        assert_eq!(ByteOrder::detect(&n64), Some(ByteOrder::N64));
+        let mut converted = n64.clone();
+        ByteOrder::N64.convert(&mut converted);
+        assert_eq!(converted, z64);
+        
+        for order in [ByteOrder::Z64, ByteOrder::V64, ByteOrder::N64] {
+            let mut written = vec![];
+            rom.write_to_with_order(&mut written, order).unwrap();
+            assert_eq!(written, rom.to_vec_with_order(order), "{order:?}");
+        }
+    }
+    
+    #[test]
+    fn fill_only_applies_to_tail_padding() {
+        let elf = minimal_elf().build_elf().unwrap();
+        let (rom, layout) = RomBuilder::new(&elf, &[0x00; 0xFC0]).fill(0xA5).build_with_layout().unwrap();
+        
+        assert_eq!(rom.binary.len(), 0x100000);
+        assert_eq!(rom.binary.last(), Some(&0xA5));
+        assert_eq!(rom.to_vec().last(), Some(&0xA5));
+        
+        // the gap between .rodata (0x14 bytes) and .data is still zeros
+        let data = layout.iter().find(|placement| placement.name == ".data").unwrap();
+        assert_eq!(data.padding_before, 0xC);
+        let gap = data.rom_offset - rom.binary_offset() - data.padding_before;
+        assert_eq!(&rom.binary[gap..gap + 0xC], &[0x00; 0xC]);
+        
+        let rom = RomBuilder::new(&elf, &[0x00; 0xFC0]).build().unwrap();
+        assert_eq!(rom.binary.last(), Some(&0xFF));
+    }
+    
+    #[test]
+    fn overlapping_sections_are_an_error() {
+        let elf = ElfBuilder::new(0x80000400)
+            .text(".boot", 0x80000400, vec![0x00; 0x100])
+            .rodata(".rodata", 0x80000500, vec![0x11; 0x40])
+            .data(".data", 0x80000520, vec![0x22; 0x40])
+            .build_elf()
+            .unwrap();
+        
+        let result = RomBuilder::new(&elf, &[0x00; 0xFC0]).build();
+        assert!(matches!(result, Err(Error::OverlappingSections(a, b)) if a == ".rodata" && b == ".data"));
+        
+        let result = RomBuilder::new(&elf, &[0x00; 0xFC0]).sections(vec![".boot".into(), ".data".into()]).build();
+        assert!(result.is_ok());
+    }
+    
+    /// Pseudo-random data, so every bit of the checksum algorithm is exercised.
+    fn random_binary(len: usize) -> Vec<u8> {
+        let mut state = 0x12345678u32;
+        (0..len).map(|_| {
+            state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+            (state >> 24) as u8
+        }).collect()
+    }
+    
+    /// The checksum as originally implemented (copying the binary into `Bytes`), excluding the
+    /// 6105 which only supported its first 16 table words.
+    fn reference_checksum(binary: &[u8], seed: u32, variant: CicVariant) -> u64 {
+        let mut t1 = Wrapping(seed);
+        let mut t2 = Wrapping(seed);
+        let mut t3 = Wrapping(seed);
+        let mut t4 = Wrapping(seed);
+        let mut t5 = Wrapping(seed);
+        let mut t6 = Wrapping(seed);
+        
+        let mut data = Bytes::from(binary[..0x100000].to_vec());
+        while data.has_remaining() {
+            let word = data.get_u32();
+            
+            let rot = Wrapping(word.rotate_left(word & 0x1F));
+            let word = Wrapping(word);
+            
+            if (t6 + word) < t6 {
+                t4 += Wrapping(1);
+            }
+            
+            t6 += word;
+            t3 ^= word;
+            t5 += rot;
+            
+            if t2 > word {
+                t2 ^= rot;
+            } else {
+                t2 ^= t6 ^ word;
+            }
+            
+            t1 += t5 ^ word;
+        }
+        
+        match variant {
+            CicVariant::Cic6103 => ((((t6 ^ t4) + t3).0 as u64) << 32) | (((t5 ^ t2) + t1).0 as u64),
+            CicVariant::Cic6106 => ((((t6 * t4) + t3).0 as u64) << 32) | (((t5 * t2) + t1).0 as u64),
+            _ => ((((t6 ^ t4) ^ t3).0 as u64) << 32) | (((t5 ^ t2) ^ t1).0 as u64),
+        }
+    }
+    
+    #[test]
+    fn checksum_matches_original_implementation() {
+        let binary = random_binary(0x100000);
+        for variant in [CicVariant::Cic6102, CicVariant::Cic6103, CicVariant::Cic6106] {
+            let seed = variant.seed().unwrap();
+            let expected = reference_checksum(&binary, seed, variant);
+            assert_eq!(Header::calculate_checksum_with_seed(&binary, [0x00; 0xFC0], seed, variant), expected, "{variant}");
+        }
+        
+        let ipl3: [u8; 0xFC0] = LIBDRAGON_IPL3_COMPAT[0x40..].try_into().unwrap();
+        let expected = reference_checksum(&binary, CicVariant::Cic6102.seed().unwrap(), CicVariant::Cic6102);
+        assert_eq!(Header::calculate_checksum(&binary, ipl3), expected);
+    }
+    
+    #[test]
+    fn short_binary_checksum_is_zero_padded() {
+        let ipl3: [u8; 0xFC0] = LIBDRAGON_IPL3_COMPAT[0x40..].try_into().unwrap();
+        let binary = random_binary(0x1000);
+        let mut padded = binary.clone();
+        padded.resize(0x100000, 0x00);
+        
+        assert_eq!(Header::calculate_checksum(&binary, ipl3), Header::calculate_checksum(&padded, ipl3));
+        assert_eq!(Header::calculate_checksum(&[], ipl3), Header::calculate_checksum(&[0x00; 0x100000], ipl3));
+    }
+    
+    #[test]
+    fn pad_to_keeps_checksum() {
+        let elf = minimal_elf().build_elf().unwrap();
+        let mut rom = RomBuilder::new(&elf, &LIBDRAGON_IPL3_COMPAT[0x40..]).build().unwrap();
+        let checksum = rom.header.checksum;
+        
+        rom.pad_to(0x800000).unwrap();
+        assert_eq!(rom.total_size(), 0x800000);
+        assert_eq!(rom.to_vec().len(), 0x800000);
+        assert_eq!(rom.header.checksum, checksum);
+        assert_eq!(rom.verify_checksum(), Some(true));
+        
+        rom.pad_to(0x800000).unwrap();
+        assert_eq!(rom.total_size(), 0x800000);
+        assert!(matches!(rom.pad_to(0x400000), Err(Error::RomTooLarge(0x800000, 0x400000))));
+    }
+    
+    #[test]
+    fn section_matches_prefix_specs() {
+        assert!(section_matches(".text", ".text"));
+        assert!(!section_matches(".text", ".text.hot"));
+        assert!(section_matches(".text*", ".text"));
+        assert!(section_matches(".text*", ".text.boot"));
+        assert!(section_matches(".text*", ".text.hot"));
+        assert!(!section_matches(".text*", ".rodata"));
+        assert!(section_matches("*", ".anything"));
+    }
+    
+    #[test]
+    fn prefix_specs_include_suffixed_sections() {
+        let elf = ElfBuilder::new(0x80000400)
+            .text(".boot", 0x80000400, vec![0x00; 0x100])
+            .text(".text", 0x80000500, vec![0x11; 0x10])
+            .text(".text.hot", 0x80000510, vec![0x22; 0x10])
+            .text(".text.cold", 0x80000520, vec![0x33; 0x10])
+            .rodata(".rodata", 0x80000530, vec![0x44; 0x10])
+            .build_elf()
+            .unwrap();
+        
+        let names = |specs: &[&str]| -> Vec<String> {
+            elf.included_sections(specs).iter().map(|section| section.name.clone().unwrap()).collect()
+        };
+        assert_eq!(names(&[".boot", ".text*"]), [".boot", ".text", ".text.hot", ".text.cold"]);
+        assert_eq!(names(&[".boot", ".text"]), [".boot", ".text"]);
+        assert_eq!(names(&[".boot", ".text.*"]), [".boot", ".text.hot", ".text.cold"]);
+        
+        let rom = RomBuilder::new(&elf, &[0x00; 0xFC0]).sections(vec![".boot".into(), ".text*".into()]).build().unwrap();
+        assert_eq!(&rom.binary[0x100..0x130], [[0x11; 0x10], [0x22; 0x10], [0x33; 0x10]].concat());
+        assert_eq!(&rom.binary[0x130..0x140], &[0xFF; 0x10]);
+    }
+    
+    #[test]
+    fn long_names_are_truncated() {
+        let header = Header::generate(&[], &[0x00; 0xFC0], "ABCDEFGHIJKLMNOPQRSTUVWXY", 0x80000400);
+        assert_eq!(&header.image_name, b"ABCDEFGHIJKLMNOPQRST");
+    }
+    
+    #[test]
+    fn multibyte_names_are_truncated_at_char_boundaries() {
+        // each emoji is 4 bytes, so only 3 fit after `Game `, followed by 3 bytes of padding
+        let header = Header::generate(&[], &[0x00; 0xFC0], "Game 🎮🎮🎮🎮🎮", 0x80000400);
+        assert_eq!(&header.image_name[..5], b"Game ");
+        assert_eq!(&header.image_name[5..9], "🎮".as_bytes());
+        assert_eq!(std::str::from_utf8(&header.image_name).unwrap(), "Game 🎮🎮🎮   ");
+        
+        let header = Header::generate(&[], &[0x00; 0xFC0], "🎮", 0x80000400);
+        assert_eq!(std::str::from_utf8(&header.image_name).unwrap(), format!("🎮{}", " ".repeat(16)));
+    }
+    
+    #[test]
+    fn header_try_from_short_buffer() {
+        assert!(matches!(Header::try_from(&[0x00; 0x20][..]), Err(Error::TruncatedHeader(0x20))));
+        
+        let mut data = [0x00; 0x50];
+        data[..4].copy_from_slice(&[0x80, 0x37, 0x12, 0x40]);
+        let header = Header::try_from(&data[..]).unwrap();
+        assert_eq!(header.pi_regs, 0x80371240);
+        assert_eq!(header, Header::new(data[..0x40].try_into().unwrap()));
+    }
+    
+    #[test]
+    fn padded_boot_only_checksum() {
+        let elf = ElfBuilder::new(0x80000400)
+            .text(".boot", 0x80000400, (0..0x40).collect())
+            .build_elf()
+            .unwrap();
+        let rom = RomBuilder::new(&elf, &LIBDRAGON_IPL3_COMPAT[0x40..]).build().unwrap();
+        
+        // the checksum covers the 0xFF padding up to 1MB, not just .boot
+        assert_eq!(rom.binary.len(), 0x100000);
+        assert_eq!(&rom.binary[0x40..], &[0xFF; 0x100000 - 0x40][..]);
+        assert_eq!(rom.header.checksum, reference_checksum(&rom.binary, CicVariant::Cic6102.seed().unwrap(), CicVariant::Cic6102));
+        assert_eq!(rom.header.checksum, 0xDABC4FEEFDED2910);
+    }
+    
+    #[test]
+    fn padding_at_megabyte_boundaries() {
+        // (.boot size, padded binary size), where the whole ROM is 0x1000 bytes larger
+        let cases = [
+            (0x40, 0x100000),
+            (0xFFFF8, 0x100000),
+            (0x100000, 0x100000),
+            (0x100008, 0x1FF000),
+            (0x1FF000, 0x1FF000),
+            (0x1FF008, 0x2FF000),
+            (0x2FF000, 0x2FF000),
+            (0x2FF008, 0x3FF000),
+        ];
+        for (boot_len, expected) in cases {
+            let elf = ElfBuilder::new(0x80000400)
+                .text(".boot", 0x80000400, vec![0x00; boot_len])
+                .build_elf()
+                .unwrap();
+            let rom = RomBuilder::new(&elf, &[0x00; 0xFC0]).build().unwrap();
+            assert_eq!(rom.binary.len(), expected, "{boot_len:#X}");
+        }
+    }
+    
+    #[test]
+    fn cic_6105_checksum_uses_whole_table() {
+        // the 6105 IPL3 isn't redistributable, so only its 256 byte table (ipl3[0x710..0x810]) is
+        // filled in. The expected value was calculated using n64crc.c's algorithm.
+        let mut ipl3 = [0x00; 0xFC0];
+        for (i, byte) in ipl3[0x710..0x810].iter_mut().enumerate() {
+            *byte = (i as u8).wrapping_mul(0x3B).wrapping_add(0x11);
+        }
+        let binary = random_binary(0x100000);
+        
+        let seed = CicVariant::Cic6105.seed().unwrap();
+        assert_eq!(Header::calculate_checksum_with_seed(&binary, ipl3, seed, CicVariant::Cic6105), 0xB5C8CEF1FAB7B1C3);
+    }
+    
+    #[test]
+    fn set_name_lengths() {
+        let mut header = Header::generate(&[], &[0x00; 0xFC0], "placeholder", 0x80000400);
+        
+        header.set_name("");
+        assert_eq!(&header.image_name, &[b' '; 20]);
+        header.set_name("ABCDEFGHIJKLMNOPQRST");
+        assert_eq!(&header.image_name, b"ABCDEFGHIJKLMNOPQRST");
+        header.set_name("ABCDEFGHIJKLMNOPQRSTU");
+        assert_eq!(&header.image_name, b"ABCDEFGHIJKLMNOPQRST");
+        
+        let elf = minimal_elf().build_elf().unwrap();
+        let mut rom = RomBuilder::new(&elf, &LIBDRAGON_IPL3_COMPAT[0x40..]).build().unwrap();
+        rom.set_name("Renamed");
+        assert_eq!(&rom.header.image_name, b"Renamed             ");
+        assert_eq!(rom.verify_checksum(), Some(true));
+    }
+    
+    #[test]
+    fn compat_ipl3_uses_6102_checksum() {
+        let ipl3: [u8; 0xFC0] = LIBDRAGON_IPL3_COMPAT[0x40..].try_into().unwrap();
+        assert_eq!(CRC.checksum(&ipl3), CicVariant::CRC_LIBDRAGON_COMPAT);
+        assert_eq!(CicVariant::detect(&ipl3), CicVariant::Unknown);
+        assert_eq!(CicVariant::detect_checksum(&ipl3), CicVariant::Cic6102);
+        
+        let elf = minimal_elf().build_elf().unwrap();
+        let rom = RomBuilder::new(&elf, &ipl3).build().unwrap();
+        let expected = Header::calculate_checksum_with_seed(&rom.binary, ipl3, CicVariant::Cic6102.seed().unwrap(), CicVariant::Cic6102);
+        assert_ne!(rom.header.checksum, 0);
+        assert_eq!(rom.header.checksum, expected);
+    }
+    
+    /// Replaces the last 4 bytes of `ipl3` so its [`CRC`] is `target`. The retail IPL3s aren't
+    /// redistributable, so this is how tests get an IPL3 detected as a CIC other than the 6102.
+    fn forge_crc(mut ipl3: [u8; 0xFC0], target: u32) -> [u8; 0xFC0] {
+        let table: Vec<u32> = (0..256).map(|i| (0..8).fold(i, |crc, _| match crc & 1 {
+            1 => (crc >> 1) ^ 0xEDB88320,
+            _ => crc >> 1,
+        })).collect();
+        
+        // run the CRC backwards from the target through the last 4 bytes
+        let mut crc = !target;
+        for _ in 0..4 {
+            let index = table.iter().position(|entry| entry >> 24 == crc >> 24).unwrap();
+            crc = ((crc ^ table[index]) << 8) | index as u32;
+        }
+        
+        let prefix_crc = !CRC.checksum(&ipl3[..0xFBC]);
+        ipl3[0xFBC..].copy_from_slice(&(crc ^ prefix_crc).to_le_bytes());
+        ipl3
+    }
+    
+    #[test]
+    fn replace_ipl3_updates_checksum() {
+        let compat: [u8; 0xFC0] = LIBDRAGON_IPL3_COMPAT[0x40..].try_into().unwrap();
+        let cic_6105 = forge_crc(compat, CicVariant::CRC_6105);
+        assert_eq!(CicVariant::detect_checksum(&cic_6105), CicVariant::Cic6105);
+        
+        let elf = minimal_elf().build_elf().unwrap();
+        let mut rom = RomBuilder::new(&elf, &compat).build().unwrap();
+        let compat_checksum = rom.header.checksum;
+        assert_eq!(compat_checksum, Header::calculate_checksum_with_seed(&rom.binary, compat, CicVariant::Cic6102.seed().unwrap(), CicVariant::Cic6102));
+        assert_eq!(rom.verify_checksum(), Some(true));
+        
+        rom.replace_ipl3(cic_6105);
+        assert_eq!(rom.ipl3, cic_6105);
+        assert_ne!(rom.header.checksum, compat_checksum);
+        assert_eq!(rom.header.checksum, Header::calculate_checksum_with_seed(&rom.binary, cic_6105, CicVariant::Cic6105.seed().unwrap(), CicVariant::Cic6105));
+        assert_eq!(rom.verify_checksum(), Some(true));
+        
+        rom.replace_ipl3(compat);
+        assert_eq!(rom.header.checksum, compat_checksum);
+        assert_eq!(rom.verify_checksum(), Some(true));
+    }
+    
+    #[test]
+    fn edit_binary_always_updates_checksum() {
+        let elf = minimal_elf().build_elf().unwrap();
+        let mut rom = RomBuilder::new(&elf, &LIBDRAGON_IPL3_COMPAT[0x40..]).build().unwrap();
+        let checksum = rom.header.checksum;
+        
+        rom.header.checksum = 0;
+        rom.edit_binary(|_| {});
+        assert_eq!(rom.header.checksum, checksum);
+    }
+    
+    #[test]
+    fn update_checksum_notices_direct_changes() {
+        let elf = minimal_elf().build_elf().unwrap();
+        let mut rom = RomBuilder::new(&elf, &LIBDRAGON_IPL3_COMPAT[0x40..]).build().unwrap();
+        rom.update_checksum();
+        let checksum = rom.header.checksum;
+        
+        rom.binary[0x80] ^= 0xFF;
+        rom.update_checksum();
+        assert_ne!(rom.header.checksum, checksum);
+        assert_eq!(rom.verify_checksum(), Some(true));
+        
+        rom.binary[0x80] ^= 0xFF;
+        rom.update_checksum();
+        assert_eq!(rom.header.checksum, checksum);
+        
+        rom.ipl3[0x100] ^= 0xFF;
+        rom.update_checksum();
+        assert_eq!(rom.header.checksum, 0);
+    }
+    
+    #[test]
+    fn appends_update_checksum_below_1mb() {
+        let elf = minimal_elf().build_elf().unwrap();
+        let mut rom = RomBuilder::new(&elf, &LIBDRAGON_IPL3_COMPAT[0x40..]).min_pad(false).build().unwrap();
+        assert!(rom.binary.len() < 0x100000);
+        
+        rom.append(&[0x12; 0x10]);
+        assert_eq!(rom.verify_checksum(), Some(true));
+        rom.append_aligned(&[0x34; 0x10], 8);
+        assert_eq!(rom.verify_checksum(), Some(true));
+        rom.append_filesystem(&[("a".into(), vec![0x56; 0x10])]);
+        assert_eq!(rom.verify_checksum(), Some(true));
+    }
+    
+    #[test]
+    fn filesystem_toc_layout() {
+        let elf = minimal_elf().build_elf().unwrap();
+        let mut rom = RomBuilder::new(&elf, &LIBDRAGON_IPL3_COMPAT[0x40..]).build().unwrap();
+        rom.append_aligned(&[0x00; 3], 1); // leave the end of the ROM unaligned
+        let files = [
+            ("music.bin".to_string(), vec![0x11; 5]),
+            ("font".to_string(), vec![0x22; 0x10]),
+        ];
+        let toc_offset = rom.append_filesystem(&files);
+        let data = rom.to_vec();
+        assert_eq!(toc_offset % 8, 0);
+        
+        let mut toc = &data[toc_offset..];
+        assert_eq!(toc.get_u32(), 2);
+        for (name, contents) in &files {
+            let offset = toc.get_u32() as usize;
+            let len = toc.get_u32() as usize;
+            let name_len = toc.get_u16() as usize;
+            assert_eq!(offset % 8, 0);
+            assert_eq!(len, contents.len());
+            assert_eq!(&data[offset..offset + len], contents);
+            assert_eq!(name_len, name.len());
+            assert_eq!(&toc[..name_len], name.as_bytes());
+            
+            // entries are padded to a multiple of 4 bytes, including the 10 bytes before the name
+            let padded_len = (10 + name_len).next_multiple_of(4) - 10;
+            assert!(toc[name_len..padded_len].iter().all(|&byte| byte == 0x00));
+            toc.advance(padded_len);
+        }
+        
+        // "music.bin" needs 1 byte of padding, "font" needs 2
+        assert_eq!(data.len() - toc.len() - toc_offset, 4 + 20 + 16);
+    }
+    
+    #[test]
+    fn header_bytes_round_trip() {
+        let header = HeaderBuilder::new()
+            .name("Round Trip")
+            .entry(0x80000400)
+            .clockrate(0x0000000F)
+            .country(b'E')
+            .media_format(b'N')
+            .cart_id(u16::from_be_bytes(*b"ZL"))
+            .revision(2)
+            .build(&[], &[0x00; 0xFC0]);
+        
+        let bytes = header.to_bytes();
+        assert_eq!(Header::new(bytes), header);
+        assert_eq!(header.to_vec(), bytes);
+        assert_eq!(&bytes[0x20..0x34], b"Round Trip          ");
+        assert_eq!(&bytes[0x3B..], b"NZLE\x02");
+    }
+}
@@ -0,0 +1,196 @@
+//! Helpers for constructing minimal N64 ELF files in memory, for testing code which uses this crate
+//! without needing a MIPS toolchain. Only available with the `testutil` feature (and in this crate's
+//! own tests).
+
+use object::elf::{EM_MIPS, ET_EXEC, SHF_ALLOC, SHF_EXECINSTR, SHF_WRITE, SHT_NOBITS, SHT_PROGBITS};
+use object::write::elf::{FileHeader, SectionHeader, Writer};
+use object::Endianness;
+use crate::elf::Elf;
+use crate::Result;
+
+/// Section to be written by an [`ElfBuilder`].
+#[derive(Clone, Debug)]
+struct Section {
+    name: String,
+    addr: u32,
+    /// Empty for uninitialized (`SHT_NOBITS`) sections.
+    data: Vec<u8>,
+    size: u32,
+    sh_type: u32,
+    flags: u32,
+}
+
+/// Builds a big-endian 32-bit MIPS executable ELF, containing only section headers (no program
+/// headers or symbols), which is all [`Elf`] needs.
+/// 
+/// ```
+/// # use nust64::testutil::ElfBuilder;
+/// let elf = ElfBuilder::new(0x80000400)
+///     .text(".boot", 0x80000400, vec![0x00; 0x40])
+///     .data(".data", 0x80000440, vec![0x12, 0x34, 0x56, 0x78])
+///     .build_elf()
+///     .unwrap();
+/// assert!(elf.section_by_name(".boot").unwrap().is_executable());
+/// ```
+#[derive(Clone, Debug)]
+pub struct ElfBuilder {
+    entry: u32,
+    sections: Vec<Section>,
+}
+impl ElfBuilder {
+    /// Starts an ELF with the provided entrypoint and no sections.
+    pub fn new(entry: u32) -> Self {
+        Self {
+            entry,
+            sections: vec![],
+        }
+    }
+    
+    /// Adds an executable section (e.g. .boot or .text).
+    pub fn text<S: Into<String>>(self, name: S, addr: u32, data: Vec<u8>) -> Self {
+        self.section(name, addr, data, SHT_PROGBITS, SHF_ALLOC | SHF_EXECINSTR)
+    }
+    
+    /// Adds a read-only data section (e.g. .rodata).
+    pub fn rodata<S: Into<String>>(self, name: S, addr: u32, data: Vec<u8>) -> Self {
+        self.section(name, addr, data, SHT_PROGBITS, SHF_ALLOC)
+    }
+    
+    /// Adds a writable data section (e.g. .data).
+    pub fn data<S: Into<String>>(self, name: S, addr: u32, data: Vec<u8>) -> Self {
+        self.section(name, addr, data, SHT_PROGBITS, SHF_ALLOC | SHF_WRITE)
+    }
+    
+    /// Adds an uninitialized section (e.g. .bss), which has a size but no data.
+    pub fn bss<S: Into<String>>(mut self, name: S, addr: u32, size: u32) -> Self {
+        self.sections.push(Section {
+            name: name.into(),
+            addr,
+            data: vec![],
+            size,
+            sh_type: SHT_NOBITS,
+            flags: SHF_ALLOC | SHF_WRITE,
+        });
+        self
+    }
+    
+    /// Adds a section with any type and flags (e.g. a non-allocated `.comment` section).
+    pub fn section<S: Into<String>>(mut self, name: S, addr: u32, data: Vec<u8>, sh_type: u32, flags: u32) -> Self {
+        self.sections.push(Section {
+            name: name.into(),
+            addr,
+            size: data.len() as u32,
+            data,
+            sh_type,
+            flags,
+        });
+        self
+    }
+    
+    /// Encodes the ELF file using `object`'s ELF writer.
+    /// 
+    /// Section data is placed directly after the file header (each aligned to 4 bytes), followed
+    /// by the section name table and the section headers.
+    pub fn build(&self) -> Vec<u8> {
+        let mut data = vec![];
+        let mut writer = Writer::new(Endianness::Big, false, &mut data);
+        
+        writer.reserve_file_header();
+        writer.reserve_null_section_index();
+        let names: Vec<_> = self.sections.iter().map(|section| {
+            writer.reserve_section_index();
+            writer.add_section_name(section.name.as_bytes())
+        }).collect();
+        writer.reserve_shstrtab_section_index();
+        let offsets: Vec<_> = self.sections.iter().map(|section| writer.reserve(section.data.len(), 4)).collect();
+        writer.reserve_shstrtab();
+        writer.reserve_section_headers();
+        
+        writer.write_file_header(&FileHeader {
+            os_abi: 0,
+            abi_version: 0,
+            e_type: ET_EXEC,
+            e_machine: EM_MIPS,
+            e_entry: self.entry as u64,
+            e_flags: 0,
+        }).expect("writing to a Vec should never fail");
+        for (section, &offset) in self.sections.iter().zip(&offsets) {
+            writer.pad_until(offset);
+            writer.write(&section.data);
+        }
+        writer.write_shstrtab();
+        
+        writer.write_null_section_header();
+        for ((section, &name), &offset) in self.sections.iter().zip(&names).zip(&offsets) {
+            writer.write_section_header(&SectionHeader {
+                name: Some(name),
+                sh_type: section.sh_type,
+                sh_flags: section.flags as u64,
+                sh_addr: section.addr as u64,
+                sh_offset: offset as u64,
+                sh_size: section.size as u64,
+                sh_link: 0,
+                sh_info: 0,
+                sh_addralign: 4,
+                sh_entsize: 0,
+            });
+        }
+        writer.write_shstrtab_section_header();
+        
+        data
+    }
+    
+    /// Encodes the ELF file, then parses it using [`Elf::from_bytes()`].
+    pub fn build_elf(&self) -> Result<Elf> {
+        Elf::from_bytes(self.build(), None)
+    }
+}
+
+/// ELF containing a .boot section at the entrypoint, followed by .text, .rodata, .data, and .bss
+/// (the default set of included sections, except .assets).
+pub fn minimal_elf() -> ElfBuilder {
+    ElfBuilder::new(0x80000400)
+        .text(".boot", 0x80000400, vec![0x00; 0x100])
+        .text(".text", 0x80000500, vec![0x24; 0x100])
+        .rodata(".rodata", 0x80000600, b"nust64 test fixture\0".to_vec())
+        .data(".data", 0x80000620, vec![0xAB; 0x20])
+        .bss(".bss", 0x80000640, 0x40)
+}
+
+#[cfg(test)]
+mod tests {
+    use object::{Object, ObjectSection, SectionFlags, SectionKind};
+    use super::*;
+    
+    #[test]
+    fn minimal_elf_parses_with_object() {
+        let raw = minimal_elf().build();
+        let file = object::File::parse(&*raw).unwrap();
+        
+        assert_eq!(file.architecture(), object::Architecture::Mips);
+        assert!(!file.is_little_endian());
+        assert_eq!(file.entry(), 0x80000400);
+        
+        let boot = file.section_by_name(".boot").unwrap();
+        assert_eq!(boot.address(), 0x80000400);
+        assert_eq!(boot.data().unwrap(), &[0x00; 0x100]);
+        assert_eq!(boot.kind(), SectionKind::Text);
+        assert_eq!(file.section_by_name(".rodata").unwrap().data().unwrap(), b"nust64 test fixture\0");
+        
+        let bss = file.section_by_name(".bss").unwrap();
+        assert_eq!(bss.kind(), SectionKind::UninitializedData);
+        assert_eq!(bss.size(), 0x40);
+        assert_eq!(bss.flags(), SectionFlags::Elf { sh_flags: (SHF_ALLOC | SHF_WRITE) as u64 });
+    }
+    
+    #[test]
+    fn build_elf_keeps_every_section() {
+        let elf = minimal_elf().build_elf().unwrap();
+        
+        for name in [".boot", ".text", ".rodata", ".data", ".bss"] {
+            assert!(elf.section_by_name(name).is_some(), "{name} is missing");
+        }
+        assert_eq!(elf.entry, 0x80000400);
+        assert_eq!(elf.section_by_name(".data").unwrap().data, vec![0xAB; 0x20]);
+    }
+}